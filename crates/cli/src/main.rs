@@ -1,9 +1,11 @@
 use anyhow::{anyhow, bail, Context, Result};
 use std::{
-    env, fs, io,
+    collections::BTreeMap,
+    env, fs,
     path::{Path, PathBuf},
     process::{Command, Stdio},
     str,
+    time::{SystemTime, UNIX_EPOCH},
 };
 use structopt::{clap::AppSettings, StructOpt};
 
@@ -30,6 +32,8 @@ enum Subcommand {
         /// Build artifacts in release mode, with optimizations
         #[structopt(long)]
         release: bool,
+        #[structopt(flatten)]
+        lock: LockOptions,
         /// Pass additional arguments directly to cargo build command
         #[structopt()]
         args: Vec<String>,
@@ -39,6 +43,8 @@ enum Subcommand {
         /// Run in release mode, with optimizations
         #[structopt(long)]
         release: bool,
+        #[structopt(flatten)]
+        lock: LockOptions,
         /// Pass additional arguments directly to cargo run command
         #[structopt()]
         args: Vec<String>,
@@ -51,11 +57,56 @@ enum Subcommand {
     },
 }
 
+/// Options of cargo controlling the use of the lockfile and the network, which apply to gfaas
+/// modules as well.
+#[derive(Debug, StructOpt)]
+struct LockOptions {
+    /// Require Cargo.lock to be up to date, and gfaas modules to use the versions it locks
+    #[structopt(long)]
+    locked: bool,
+    /// Run without accessing the network
+    #[structopt(long)]
+    offline: bool,
+    /// Equivalent to specifying both --locked and --offline
+    #[structopt(long)]
+    frozen: bool,
+}
+
+impl LockOptions {
+    fn locked(&self) -> bool {
+        self.locked || self.frozen
+    }
+
+    fn offline(&self) -> bool {
+        self.offline || self.frozen
+    }
+
+    /// Returns the options as arguments to cargo.
+    fn args(&self) -> Vec<&'static str> {
+        let mut args = vec![];
+        if self.locked() {
+            args.push("--locked");
+        }
+        if self.offline() {
+            args.push("--offline");
+        }
+        args
+    }
+}
+
 fn main() {
     let opt = Opt::from_args();
     let res = match opt.cmd {
-        Subcommand::Build { release, args } => build(release, &args),
-        Subcommand::Run { release, args } => run(release, &args),
+        Subcommand::Build {
+            release,
+            lock,
+            args,
+        } => build(release, &lock, &args),
+        Subcommand::Run {
+            release,
+            lock,
+            args,
+        } => run(release, &lock, &args),
         Subcommand::Clean { args } => clean(&args),
     };
 
@@ -64,14 +115,21 @@ fn main() {
     }
 }
 
-fn build(release: bool, args: &[String]) -> Result<()> {
+fn build(release: bool, lock: &LockOptions, args: &[String]) -> Result<()> {
     let profile = if release { "release" } else { "debug" };
-    let out_dir = Path::new("target").join(&profile);
+    // Absolute, since rustc runs the macros in the root of the workspace rather than that of
+    // the package.
+    let out_dir = env::current_dir()
+        .context("reading current dir")?
+        .join("target")
+        .join(profile);
 
-    // Fetch cargo manifest path for the root project
+    // Fetch the packages of the workspace along with their gfaas settings
     let mut cmd = Command::new("cargo");
     let cmd_out = cmd
         .arg("metadata")
+        .arg("--no-deps")
+        .arg("--format-version=1")
         .output()
         .context("running 'cargo metadata' command")?;
     let metadata: serde_json::Value = match serde_json::from_slice(&cmd_out.stdout) {
@@ -89,18 +147,11 @@ fn build(release: bool, args: &[String]) -> Result<()> {
     let workspace_root = metadata["workspace_root"]
         .as_str()
         .ok_or(anyhow!("metadata['workspace_root'] is not a UTF8 string"))?;
+    let packages = metadata["packages"]
+        .as_array()
+        .ok_or(anyhow!("metadata['packages'] is not an array"))?;
 
-    // Create cargo package with gfaas funcs
-    let module_path = out_dir.join("gfaas_modules");
-    let bin_path = module_path.join("src").join("bin");
-    if let Err(err) = fs::create_dir_all(&bin_path) {
-        match err.kind() {
-            io::ErrorKind::AlreadyExists => {}
-            _ => bail!("couldn't create gfaas_module dir: {}", err),
-        }
-    }
-
-    // Parse manifest of the workspace and extract gfaas deps
+    // Parse manifest of the workspace for the dependencies inherited by its members
     let manifest_path = Path::new(workspace_root).join("Cargo.toml");
     let contents = fs::read_to_string(&manifest_path)
         .with_context(|| format!("failed to read '{}'", manifest_path.display()))?;
@@ -110,42 +161,39 @@ fn build(release: bool, args: &[String]) -> Result<()> {
     let manifest_toml = manifest_toml
         .as_table_mut()
         .ok_or(anyhow!("malformed 'Cargo.toml'?"))?;
-
-    let mut gfaas_toml = toml::toml! {
-        [package]
-        name = "gfaas_modules"
-        version = "0.1.0"
-        edition = "2018"
-    };
-
-    gfaas_toml.as_table_mut().unwrap().insert(
-        "dependencies".to_owned(),
-        toml::toml! {
-            serde_json = "1"
-        },
-    );
-    if let Some(deps) = manifest_toml.remove("gfaas_dependencies") {
-        let gfaas_deps = gfaas_toml
-            .as_table_mut()
-            .unwrap()
-            .get_mut("dependencies")
-            .unwrap()
-            .as_table_mut()
-            .unwrap();
-
-        for key in deps.as_table().unwrap().keys() {
-            gfaas_deps.insert(key.to_owned(), deps[key].clone());
-        }
+    let workspace_deps = manifest_toml
+        .get("workspace")
+        .and_then(|workspace| workspace.get("dependencies"))
+        .and_then(toml::Value::as_table)
+        .cloned()
+        .unwrap_or_default();
+    let legacy_deps = manifest_toml.remove("gfaas_dependencies");
+    if legacy_deps.is_some() {
+        eprintln!(
+            "warning: '[gfaas_dependencies]' in '{}' is deprecated, list the dependencies in \
+             '[package.metadata.gfaas.dependencies]' instead",
+            manifest_path.display()
+        );
     }
 
-    let gfaas_toml =
-        toml::to_string(&gfaas_toml).context("couldn't serialize gfaas modules to TOML")?;
-    fs::write(module_path.join("Cargo.toml"), gfaas_toml)
-        .with_context(|| format!("saving '{}'", module_path.join("Cargo.toml").display()))?;
+    // Clear the sources generated by the previous build, so that the Wasm modules of removed
+    // or renamed gfaas funcs don't linger. A fresh build id makes cargo recompile every crate
+    // using the macros, which regenerate the rest.
+    for package in packages {
+        let name = package["name"]
+            .as_str()
+            .ok_or(anyhow!("package name is not a UTF8 string"))?;
+        clear_generated(&out_dir.join("gfaas_modules").join(name))?;
+    }
+    let build_id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("reading system time")?
+        .as_nanos();
 
     // Run cargo build
     let mut cmd = Command::new("cargo");
     cmd.arg("build")
+        .args(lock.args())
         // TODO We don't want the user to pass `--release` using aux cargo args,
         // so let's filter it out for now. In the future, we might want to
         // throw an error instead.
@@ -156,6 +204,7 @@ fn build(release: bool, args: &[String]) -> Result<()> {
         .envs(env::vars())
         .env("CARGO_TARGET_DIR", "target")
         .env("GFAAS_OUT_DIR", &out_dir)
+        .env("GFAAS_BUILD_ID", build_id.to_string())
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit());
     if release {
@@ -163,42 +212,95 @@ fn build(release: bool, args: &[String]) -> Result<()> {
     }
     let _cmd_out = cmd.output().context("failed to build the project")?;
 
-    // Next, run cargo build --target=wasm32-wasi on gfaas_modules crate.
-    let mut cmd = Command::new("cargo");
-    cmd.arg("build")
-        .arg("--bins")
-        .arg("--target=wasm32-wasi")
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .current_dir(&module_path);
-    if release {
-        cmd.arg("--release");
-    }
-    let _ = cmd.output().context("failed to build the gfaas modules")?;
+    // Every package gets gfaas modules of its own, so that the functions of different
+    // packages don't clobber each other.
+    let host_lock_path = Path::new(workspace_root).join("Cargo.lock");
+    let mut modules: BTreeMap<PathBuf, (&str, PathBuf)> = BTreeMap::new();
+    for package in packages {
+        let name = package["name"]
+            .as_str()
+            .ok_or(anyhow!("package name is not a UTF8 string"))?;
+        let module_path = out_dir.join("gfaas_modules").join(name);
+        // The package has no gfaas funcs
+        if !module_path.join("src").exists() {
+            continue;
+        }
+        let manifest_path = package["manifest_path"]
+            .as_str()
+            .ok_or(anyhow!("manifest path of '{}' is not a UTF8 string", name))?;
+        let manifest_dir = Path::new(manifest_path)
+            .parent()
+            .ok_or(anyhow!("manifest path of '{}' without parent", name))?;
+        let config = match &package["metadata"]["gfaas"] {
+            serde_json::Value::Null => None,
+            config => Some(
+                toml::Value::try_from(config)
+                    .with_context(|| format!("parsing '[package.metadata.gfaas]' of '{}'", name))?,
+            ),
+        };
+        let mut gfaas_toml = guest_manifest(
+            config.as_ref(),
+            manifest_dir,
+            &workspace_deps,
+            legacy_deps
+                .as_ref()
+                .map(|deps| (deps, Path::new(workspace_root))),
+        )
+        .with_context(|| format!("in '[package.metadata.gfaas]' of '{}'", name))?;
 
-    // Copy Wasm binaries next to the binary proper
-    let from_dir = module_path
-        .join("target")
-        .join("wasm32-wasi")
-        .join(&profile);
-    let mut entries = vec![];
-    for entry in fs::read_dir(&from_dir)? {
-        let entry = entry?;
-        let entry_path = entry.path();
+        // The codecs used by gfaas funcs are known only once the project is built
+        register_codecs(&module_path, &mut gfaas_toml)?;
+
+        let gfaas_toml =
+            toml::to_string(&gfaas_toml).context("couldn't serialize gfaas modules to TOML")?;
+        fs::write(module_path.join("Cargo.toml"), gfaas_toml)
+            .with_context(|| format!("saving '{}'", module_path.join("Cargo.toml").display()))?;
+
+        // Pin the dependencies shared with the project to the versions locked by it, so that
+        // gfaas modules are built just like the project itself.
+        if host_lock_path.exists() {
+            seed_lockfile(&host_lock_path, &module_path, lock)?;
+        }
 
-        if let Some(ext) = entry_path.extension() {
-            if ext == "wasm" {
-                entries.push(PathBuf::from(entry_path.file_name().unwrap()));
+        // Next, run cargo build --target=wasm32-wasi on gfaas_modules crate.
+        let mut cmd = Command::new("cargo");
+        cmd.arg("build")
+            .arg("--bins")
+            .arg("--target=wasm32-wasi")
+            .args(lock.args())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .current_dir(&module_path);
+        if release {
+            cmd.arg("--release");
+        }
+        let cmd_out = cmd.output().context("failed to build the gfaas modules")?;
+        if !cmd_out.status.success() {
+            bail!("building gfaas modules of '{}' failed", name);
+        }
+
+        // Only the modules of this build, as the target dir keeps those of the previous ones
+        let from_dir = module_path.join("target").join("wasm32-wasi").join(profile);
+        for bin in module_bins(&module_path)? {
+            let file_name = PathBuf::from(format!("{}.wasm", bin));
+            if let Some((other, _)) = modules.get(&file_name) {
+                bail!(
+                    "both '{}' and '{}' define Wasm module '{}'",
+                    other,
+                    name,
+                    file_name.display()
+                );
             }
+            modules.insert(file_name.clone(), (name, from_dir.join(file_name)));
         }
     }
 
-    if entries.is_empty() {
+    if modules.is_empty() {
         bail!("no Wasm modules were generated!");
     }
 
-    for entry in entries {
-        let from_path = from_dir.join(&entry);
+    // Copy Wasm binaries next to the binary proper
+    for (entry, (_, from_path)) in modules {
         let to_path = out_dir.join(entry);
         fs::copy(&from_path, &to_path).with_context(|| {
             format!(
@@ -212,14 +314,331 @@ fn build(release: bool, args: &[String]) -> Result<()> {
     Ok(())
 }
 
-fn run(release: bool, args: &[String]) -> Result<()> {
+/// Removes the sources of the Wasm modules generated into `module_path`, along with the codecs
+/// registered by them.
+fn clear_generated(module_path: &Path) -> Result<()> {
+    for dir in &["src", "codecs"] {
+        let path = module_path.join(dir);
+        if path.exists() {
+            fs::remove_dir_all(&path).with_context(|| format!("removing '{}'", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the names of the binaries, i.e., Wasm modules, generated into `module_path`. As with
+/// cargo, a dir counts only if it has `main.rs`, which is not the case for a type annotated
+/// with `gfaas::remote_impl` without any of its impl blocks being annotated as well.
+fn module_bins(module_path: &Path) -> Result<Vec<String>> {
+    let bin_path = module_path.join("src").join("bin");
+    if !bin_path.exists() {
+        return Ok(vec![]);
+    }
+    let entries =
+        fs::read_dir(&bin_path).with_context(|| format!("listing '{}'", bin_path.display()))?;
+    let mut bins = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        let name = if path.join("main.rs").is_file() {
+            path.file_name()
+        } else if path.extension() == Some("rs".as_ref()) {
+            path.file_stem()
+        } else {
+            None
+        };
+        bins.extend(name.and_then(|name| name.to_str()).map(ToOwned::to_owned));
+    }
+    bins.sort();
+    Ok(bins)
+}
+
+/// Returns the dependency `dep` named `key` as specified in the manifest found in `manifest_dir`,
+/// with its path made absolute, so that it can be used by gfaas modules, and resolved against
+/// `workspace_deps` if it's inherited from the workspace, i.e., `key = { workspace = true }`.
+fn resolve_dependency(
+    key: &str,
+    dep: &toml::Value,
+    manifest_dir: &Path,
+    workspace_deps: &toml::value::Table,
+) -> Result<toml::Value> {
+    let mut dep = match dep.as_table() {
+        Some(table) => table.clone(),
+        // A version requirement only, e.g., `log = "0.4"`.
+        None => return Ok(dep.clone()),
+    };
+    if dep.remove("workspace").and_then(|x| x.as_bool()) == Some(true) {
+        let inherited = workspace_deps.get(key).ok_or(anyhow!(
+            "dependency '{}' not found in 'workspace.dependencies'",
+            key
+        ))?;
+        let mut inherited = match inherited {
+            toml::Value::Table(table) => table.clone(),
+            version => {
+                let mut table = toml::value::Table::new();
+                table.insert("version".to_owned(), version.clone());
+                table
+            }
+        };
+        // The features listed by the member add up to the ones of the workspace, while the
+        // rest of the keys (e.g., `optional`) override them.
+        if let Some(toml::Value::Array(features)) = dep.remove("features") {
+            match inherited.get_mut("features") {
+                Some(toml::Value::Array(inherited)) => inherited.extend(features),
+                _ => {
+                    inherited.insert("features".to_owned(), toml::Value::Array(features));
+                }
+            }
+        }
+        inherited.extend(dep);
+        dep = inherited;
+    }
+    if let Some(path) = dep.get_mut("path") {
+        let relative = path
+            .as_str()
+            .ok_or(anyhow!("path of dependency '{}' is not a string", key))?;
+        let absolute = manifest_dir.join(relative);
+        *path = toml::Value::String(absolute.to_string_lossy().into_owned());
+    }
+    Ok(toml::Value::Table(dep))
+}
+
+/// Returns the manifest of gfaas modules of a package configured with `config`, i.e., its
+/// `[package.metadata.gfaas]` table, whose paths are relative to `manifest_dir`. The
+/// deprecated `[gfaas_dependencies]` of the workspace, if any, come with the dir they're
+/// relative to.
+fn guest_manifest(
+    config: Option<&toml::Value>,
+    manifest_dir: &Path,
+    workspace_deps: &toml::value::Table,
+    legacy_deps: Option<(&toml::Value, &Path)>,
+) -> Result<toml::Value> {
+    let mut gfaas_toml = toml::toml! {
+        [package]
+        name = "gfaas_modules"
+        version = "0.1.0"
+        edition = "2018"
+    };
+    let gfaas_table = gfaas_toml.as_table_mut().unwrap();
+    // The modules live in the target dir, possibly within the user's workspace, which they're
+    // not a member of.
+    gfaas_table.insert(
+        "workspace".to_owned(),
+        toml::Value::Table(Default::default()),
+    );
+
+    let mut gfaas_deps = toml::value::Table::new();
+    gfaas_deps.insert("serde_json".to_owned(), toml::Value::from("1"));
+    if let Some((deps, root)) = legacy_deps {
+        let deps = deps
+            .as_table()
+            .ok_or(anyhow!("'gfaas_dependencies' is not a table"))?;
+        for (key, dep) in deps {
+            let dep = resolve_dependency(key, dep, root, workspace_deps)?;
+            gfaas_deps.insert(key.to_owned(), dep);
+        }
+    }
+    let empty = toml::value::Table::new();
+    let config = match config {
+        Some(config) => config.as_table().ok_or(anyhow!("not a table"))?,
+        None => &empty,
+    };
+    if let Some(deps) = config.get("dependencies") {
+        let deps = deps
+            .as_table()
+            .ok_or(anyhow!("'dependencies' is not a table"))?;
+        for (key, dep) in deps {
+            let dep = resolve_dependency(key, dep, manifest_dir, workspace_deps)?;
+            gfaas_deps.insert(key.to_owned(), dep);
+        }
+    }
+    if let Some(path) = config.get("guest_crate") {
+        let path = path
+            .as_str()
+            .ok_or(anyhow!("'guest_crate' is not a path"))?;
+        let (name, dep) = guest_crate(&manifest_dir.join(path))?;
+        gfaas_deps.insert(name, dep);
+    }
+    gfaas_table.insert("dependencies".to_owned(), toml::Value::Table(gfaas_deps));
+
+    // Profiles apply only to the root of a workspace, which gfaas modules are.
+    if let Some(profile) = config.get("profile") {
+        if !profile.is_table() {
+            bail!("'profile' is not a table");
+        }
+        gfaas_table.insert("profile".to_owned(), profile.clone());
+    }
+    Ok(gfaas_toml)
+}
+
+/// Returns the name of the crate found in `path`, and the dependency of gfaas modules on it,
+/// which exposes its public API to every gfaas func.
+fn guest_crate(path: &Path) -> Result<(String, toml::Value)> {
+    let manifest_path = path.join("Cargo.toml");
+    let contents = fs::read_to_string(&manifest_path)
+        .with_context(|| format!("failed to read '{}'", manifest_path.display()))?;
+    let manifest_toml = contents
+        .parse::<toml::Value>()
+        .with_context(|| format!("parsing contents of '{}' as TOML", manifest_path.display()))?;
+    let name = manifest_toml
+        .get("package")
+        .and_then(|package| package.get("name"))
+        .and_then(toml::Value::as_str)
+        .ok_or(anyhow!(
+            "guest crate '{}' has no package name",
+            manifest_path.display()
+        ))?;
+    let mut dep = toml::value::Table::new();
+    dep.insert(
+        "path".to_owned(),
+        toml::Value::String(path.to_string_lossy().into_owned()),
+    );
+    Ok((name.to_owned(), toml::Value::Table(dep)))
+}
+
+fn read_lockfile(path: &Path) -> Result<toml::Value> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("failed to read '{}'", path.display()))?;
+    contents
+        .parse::<toml::Value>()
+        .with_context(|| format!("parsing contents of '{}' as TOML", path.display()))
+}
+
+/// Seeds the lockfile of gfaas modules in `module_path` from the one of the project found in
+/// `host_lock_path`, and resolves the dependencies which the latter doesn't cover.
+///
+/// With `--locked`, fails if any of them had to be resolved, otherwise warns about the ones
+/// resolved to versions other than the project's.
+fn seed_lockfile(host_lock_path: &Path, module_path: &Path, lock: &LockOptions) -> Result<()> {
+    let guest_lock_path = module_path.join("Cargo.lock");
+    fs::copy(host_lock_path, &guest_lock_path).with_context(|| {
+        format!(
+            "seeding '{}' from '{}'",
+            guest_lock_path.display(),
+            host_lock_path.display()
+        )
+    })?;
+
+    // Updating the workspace only adds gfaas modules to the lockfile, and whatever they depend
+    // on which the project doesn't, leaving the rest of the versions be.
+    let mut cmd = Command::new("cargo");
+    cmd.arg("update")
+        .arg("--workspace")
+        .args(lock.args().into_iter().filter(|x| *x == "--offline"))
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .current_dir(module_path);
+    let cmd_out = cmd
+        .output()
+        .context("failed to update the lockfile of gfaas modules")?;
+    if !cmd_out.status.success() {
+        bail!("updating the lockfile of gfaas modules failed");
+    }
+
+    let host_lock = read_lockfile(host_lock_path)?;
+    let guest_lock = read_lockfile(&guest_lock_path)?;
+    let unlocked = unlocked_packages(&host_lock, &guest_lock);
+    if lock.locked() && !unlocked.is_empty() {
+        let packages: Vec<_> = unlocked
+            .iter()
+            .map(|(name, version, _)| format!("{} v{}", name, version))
+            .collect();
+        bail!(
+            "gfaas modules depend on packages not locked by '{}': {}",
+            host_lock_path.display(),
+            packages.join(", ")
+        );
+    }
+    for (name, version, host_versions) in unlocked {
+        if !host_versions.is_empty() {
+            eprintln!(
+                "warning: gfaas modules use {} v{}, while the project uses v{}",
+                name,
+                version,
+                host_versions.join(", v"),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns the name and version of every package from a registry or a git repository locked in
+/// `guest_lock` at a version which `host_lock` doesn't lock it at, along with the versions it
+/// does, if any.
+fn unlocked_packages(
+    host_lock: &toml::Value,
+    guest_lock: &toml::Value,
+) -> Vec<(String, String, Vec<String>)> {
+    let packages = |lock: &toml::Value| -> Vec<(String, String)> {
+        lock.get("package")
+            .and_then(toml::Value::as_array)
+            .map(|packages| {
+                packages
+                    .iter()
+                    // Path packages, i.e., ones without a source, are local anyway.
+                    .filter(|package| package.get("source").is_some())
+                    .filter_map(|package| {
+                        let name = package.get("name")?.as_str()?;
+                        let version = package.get("version")?.as_str()?;
+                        Some((name.to_owned(), version.to_owned()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let host_packages = packages(host_lock);
+    let mut unlocked = vec![];
+    for (name, version) in packages(guest_lock) {
+        let host_versions: Vec<_> = host_packages
+            .iter()
+            .filter(|(host_name, _)| *host_name == name)
+            .map(|(_, host_version)| host_version.clone())
+            .collect();
+        if !host_versions.contains(&version) {
+            unlocked.push((name, version, host_versions));
+        }
+    }
+    unlocked
+}
+
+/// Adds the crates of the codecs registered by gfaas funcs in `module_path` to the
+/// dependencies of gfaas modules.
+fn register_codecs(module_path: &Path, gfaas_toml: &mut toml::Value) -> Result<()> {
+    let codecs_path = module_path.join("codecs");
+    if !codecs_path.exists() {
+        return Ok(());
+    }
+    let gfaas_deps = gfaas_toml
+        .as_table_mut()
+        .unwrap()
+        .get_mut("dependencies")
+        .unwrap()
+        .as_table_mut()
+        .unwrap();
+    for entry in fs::read_dir(&codecs_path)? {
+        let entry = entry?;
+        let (name, version) = match entry.file_name().to_str() {
+            Some("bincode") => ("bincode", "1.3"),
+            Some("cbor") => ("serde_cbor", "0.11"),
+            Some("msgpack") => ("rmp-serde", "0.13"),
+            _ => continue,
+        };
+        // Don't override the version specified by the user in gfaas_dependencies.
+        if !gfaas_deps.contains_key(name) {
+            gfaas_deps.insert(name.to_owned(), toml::Value::String(version.to_owned()));
+        }
+    }
+    Ok(())
+}
+
+fn run(release: bool, lock: &LockOptions, args: &[String]) -> Result<()> {
     // We need to run cargo build first so that the Wasm artifacts are properly
     // generated.
-    build(release, &[])?;
+    build(release, lock, &[])?;
 
     // Run cargo run
     let mut cmd = Command::new("cargo");
     cmd.arg("run")
+        .args(lock.args())
         // TODO We don't want the user to pass `--release` using aux cargo args,
         // so let's filter it out for now. In the future, we might want to
         // throw an error instead.
@@ -251,3 +670,174 @@ fn clean(args: &[String]) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_dependencies() {
+        let manifest = r#"
+            [workspace.dependencies]
+            log = "0.4"
+            kernels = { path = "crates/kernels", features = ["simd"] }
+
+            [gfaas_dependencies]
+            rand = "0.7"
+            utils = { path = "../utils" }
+            log = { workspace = true, optional = true }
+            kernels = { workspace = true, features = ["std"] }
+            missing = { workspace = true }
+        "#
+        .parse::<toml::Value>()
+        .unwrap();
+        let workspace_deps = manifest["workspace"]["dependencies"].as_table().unwrap();
+        let resolve = |key| {
+            let dep = &manifest["gfaas_dependencies"][key];
+            resolve_dependency(key, dep, Path::new("/ws"), workspace_deps)
+        };
+        let expected = r#"
+            rand = "0.7"
+            utils = { path = "/ws/../utils" }
+            log = { version = "0.4", optional = true }
+            kernels = { path = "/ws/crates/kernels", features = ["simd", "std"] }
+        "#
+        .parse::<toml::Value>()
+        .unwrap();
+
+        for (key, dep) in expected.as_table().unwrap() {
+            assert_eq!(&resolve(key).unwrap(), dep, "{}", key);
+        }
+        assert!(resolve("missing").is_err());
+    }
+
+    #[test]
+    fn writes_guest_manifest() {
+        let config = r#"
+            dependencies = { log = "0.4", utils = { path = "../utils" } }
+            profile = { release = { opt-level = "s" } }
+        "#
+        .parse::<toml::Value>()
+        .unwrap();
+        let legacy_deps = toml::toml! {
+            rand = "0.7"
+            log = "0.3"
+        };
+        let manifest = guest_manifest(
+            Some(&config),
+            Path::new("/ws/app"),
+            &Default::default(),
+            Some((&legacy_deps, Path::new("/ws"))),
+        )
+        .unwrap();
+        let expected = r#"
+            workspace = {}
+
+            [package]
+            name = "gfaas_modules"
+            version = "0.1.0"
+            edition = "2018"
+
+            [dependencies]
+            serde_json = "1"
+            rand = "0.7"
+            log = "0.4"
+            utils = { path = "/ws/app/../utils" }
+
+            [profile.release]
+            opt-level = "s"
+        "#
+        .parse::<toml::Value>()
+        .unwrap();
+        assert_eq!(manifest, expected);
+
+        let config = toml::toml! { profile = "release" };
+        assert!(
+            guest_manifest(Some(&config), Path::new("/ws"), &Default::default(), None).is_err()
+        );
+    }
+
+    #[test]
+    fn finds_unlocked_packages() {
+        let host_lock = r#"
+            [[package]]
+            name = "app"
+            version = "0.1.0"
+
+            [[package]]
+            name = "serde_json"
+            version = "1.0.60"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "rand"
+            version = "0.6.5"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "rand"
+            version = "0.7.3"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+        "#
+        .parse::<toml::Value>()
+        .unwrap();
+        let guest_lock = r#"
+            [[package]]
+            name = "gfaas_modules"
+            version = "0.1.0"
+
+            [[package]]
+            name = "serde_json"
+            version = "1.0.61"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "rand"
+            version = "0.7.3"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "bincode"
+            version = "1.3.1"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+        "#
+        .parse::<toml::Value>()
+        .unwrap();
+        assert_eq!(
+            unlocked_packages(&host_lock, &guest_lock),
+            vec![
+                (
+                    "serde_json".to_owned(),
+                    "1.0.61".to_owned(),
+                    vec!["1.0.60".to_owned()]
+                ),
+                ("bincode".to_owned(), "1.3.1".to_owned(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn clears_generated_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let module_path = dir.path();
+        fs::create_dir_all(module_path.join("src/bin/app-group")).unwrap();
+        fs::create_dir_all(module_path.join("src/bin/app-Point")).unwrap();
+        fs::create_dir_all(module_path.join("codecs")).unwrap();
+        fs::write(module_path.join("src/bin/app-hello.rs"), "").unwrap();
+        fs::write(module_path.join("src/bin/app-group/main.rs"), "").unwrap();
+        fs::write(module_path.join("src/bin/app-Point/type.rs"), "").unwrap();
+        fs::write(module_path.join("src/bin/notes.txt"), "").unwrap();
+        fs::write(module_path.join("Cargo.toml"), "").unwrap();
+        assert_eq!(
+            module_bins(module_path).unwrap(),
+            vec!["app-group".to_owned(), "app-hello".to_owned()]
+        );
+
+        clear_generated(module_path).unwrap();
+        assert!(!module_path.join("src").exists());
+        assert!(!module_path.join("codecs").exists());
+        assert!(module_path.join("Cargo.toml").exists());
+        assert!(module_bins(module_path).unwrap().is_empty());
+        clear_generated(module_path).unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+//! Parser of the constraints on the providers given in the syntax of Golem's demands, shared by
+//! the gfaas crate, which adds them to the demands, and its macros, which reject the malformed
+//! ones at compile time.
+use std::fmt;
+
+/// Expression of the constraints.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    /// Clause of one or more expressions, e.g., `(|(a=b)(c))`.
+    Clause(Operator, Vec<Expr>),
+    /// Property name optionally compared with a value, e.g., `(a=b)` or `(c)`.
+    Property(String, Option<(Comparison, String)>),
+}
+
+/// Operator of a clause.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    /// `&`, i.e., all of the expressions have to be satisfied.
+    And,
+    /// `|`, i.e., any of the expressions has to be satisfied.
+    Or,
+}
+
+/// Comparison of a property with a value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    /// `=`
+    Equal,
+    /// `<>`
+    NotEqual,
+    /// `<`
+    Less,
+    /// `>`
+    Greater,
+}
+
+/// Error returned for malformed constraints.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Parses `input` consisting of one or more parenthesized expressions, all of which have to be
+/// satisfied, e.g., `(golem.inf.cpu.threads>3)(|(golem.node.id.name=a)(golem.node.id.name=b))`.
+///
+/// Every expression is either a clause, i.e., `&` or `|` followed by one or more expressions,
+/// or a property name optionally followed by one of `=`, `<>`, `<`, or `>`, and a value.
+pub fn parse(input: &str) -> Result<Vec<Expr>, Error> {
+    let mut parser = Parser { input, pos: 0 };
+    let mut exprs = vec![];
+    while !parser.at_end() {
+        exprs.push(parser.expr()?);
+    }
+    if exprs.is_empty() {
+        return Err(Error("expected at least one constraint".to_owned()));
+    }
+    Ok(exprs)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.rest().is_empty()
+    }
+
+    /// Consumes `token` if the input continues with it.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.rest().starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), Error> {
+        if !self.eat(token) {
+            return Err(Error(format!(
+                "expected '{}' at position {}",
+                token, self.pos
+            )));
+        }
+        Ok(())
+    }
+
+    /// Consumes everything up to, but excluding, any of the `delimiters`, and returns it
+    /// trimmed unless it's empty.
+    fn take_until(&mut self, delimiters: &[char], what: &str) -> Result<&'a str, Error> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest.find(delimiters).unwrap_or(rest.len());
+        let taken = rest[..len].trim();
+        if taken.is_empty() {
+            return Err(Error(format!("expected {} at position {}", what, self.pos)));
+        }
+        self.pos += len;
+        Ok(taken)
+    }
+
+    fn expr(&mut self) -> Result<Expr, Error> {
+        self.expect("(")?;
+        let clause = if self.eat("&") {
+            Some(Operator::And)
+        } else if self.eat("|") {
+            Some(Operator::Or)
+        } else {
+            None
+        };
+        let expr = match clause {
+            Some(operator) => {
+                let mut exprs = vec![self.expr()?];
+                while !self.at_end() && !self.rest().starts_with(')') {
+                    exprs.push(self.expr()?);
+                }
+                Expr::Clause(operator, exprs)
+            }
+            None => self.property()?,
+        };
+        self.expect(")")?;
+        Ok(expr)
+    }
+
+    /// Parses the property name, and the comparison and value if any.
+    fn property(&mut self) -> Result<Expr, Error> {
+        let name = self.take_until(&['=', '<', '>', '(', ')'], "property name")?;
+        let comparison = if self.eat("<>") {
+            Comparison::NotEqual
+        } else if self.eat("=") {
+            Comparison::Equal
+        } else if self.eat("<") {
+            Comparison::Less
+        } else if self.eat(">") {
+            Comparison::Greater
+        } else {
+            return Ok(Expr::Property(name.to_owned(), None));
+        };
+        let value = self.take_until(&['(', ')'], "value")?;
+        Ok(Expr::Property(
+            name.to_owned(),
+            Some((comparison, value.to_owned())),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn property(name: &str, comparison: Option<(Comparison, &str)>) -> Expr {
+        Expr::Property(
+            name.to_owned(),
+            comparison.map(|(comparison, value)| (comparison, value.to_owned())),
+        )
+    }
+
+    #[test]
+    fn parses_expressions() {
+        let exprs = parse(
+            " (golem.inf.cpu.threads > 3)(|(golem.node.id.name=a b)(golem.node.id.name<>c))\
+             (&(golem.runtime.name)) ",
+        )
+        .unwrap();
+        assert_eq!(
+            exprs,
+            vec![
+                property("golem.inf.cpu.threads", Some((Comparison::Greater, "3"))),
+                Expr::Clause(
+                    Operator::Or,
+                    vec![
+                        property("golem.node.id.name", Some((Comparison::Equal, "a b"))),
+                        property("golem.node.id.name", Some((Comparison::NotEqual, "c"))),
+                    ],
+                ),
+                Expr::Clause(Operator::And, vec![property("golem.runtime.name", None)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        for input in &[
+            "",
+            "golem.runtime.name",
+            "(=a)",
+            "(a=)",
+            "(&)",
+            "(a=b",
+            "(a=b))",
+            "(a=b)c",
+        ] {
+            assert!(parse(input).is_err(), "{:?}", input);
+        }
+        assert_eq!(
+            parse("(golem.inf.cpu.threads>)").unwrap_err().to_string(),
+            "expected value at position 23"
+        );
+    }
+}
@@ -1,14 +1,110 @@
 extern crate proc_macro;
 
 mod logic;
+mod package;
+mod source;
 
+use package::PackageConfig;
 use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use source::Source;
+use std::{env, path::Path};
 use syn::parse_macro_input;
 
+/// Returns the crate which the Wasm modules of the package being compiled are generated into,
+/// i.e., `$GFAAS_OUT_DIR/gfaas_modules/<package>`, unless GFAAS_OUT_DIR is unset. The names of
+/// the Wasm modules are made of the locations of their items, so generating them fails unless
+/// the location of the item found in `source` is known, which requires Rust 1.88 or newer.
+fn out_dir(source: &Source) -> syn::Result<Option<String>> {
+    let out_dir = match env::var("GFAAS_OUT_DIR") {
+        Ok(out_dir) => out_dir,
+        Err(_) => return Ok(None),
+    };
+    if source.location.is_none() {
+        return Err(syn::Error::new(
+            Span::call_site(),
+            "the location of this item, which the name of its Wasm module is made of, is \
+             unknown: building Wasm modules requires Rust 1.88 or newer",
+        ));
+    }
+    let package = env::var("CARGO_PKG_NAME").unwrap_or_default();
+    let out_dir = Path::new(&out_dir).join("gfaas_modules").join(package);
+    Ok(Some(out_dir.to_string_lossy().into_owned()))
+}
+
+/// Makes rustc track the env variables set by gfaas build tool, so that cargo recompiles the
+/// crate, and so regenerates its Wasm module sources, on every build with the tool. The same
+/// goes for the manifest of the package, which the expansion depends on through its
+/// `[package.metadata.gfaas]` table.
+fn track_env(expanded: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    quote! {
+        #expanded
+        const _: Option<&str> = option_env!("GFAAS_OUT_DIR");
+        const _: Option<&str> = option_env!("GFAAS_BUILD_ID");
+        const _: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"));
+    }
+}
+
 #[proc_macro_attribute]
 pub fn remote_fn(attr: TokenStream, item: TokenStream) -> TokenStream {
     let attrs = parse_macro_input!(attr as logic::GwasmAttrs);
-    let preserved = item.clone();
     let f = parse_macro_input!(item as logic::GwasmFn);
-    logic::remote_fn_impl(attrs, f, preserved.into()).into()
+    // Without GFAAS_OUT_DIR, there is no Wasm module to run, unless the function runs natively.
+    PackageConfig::load()
+        .and_then(|package| {
+            let source = Source::locate();
+            let out_dir = out_dir(&source)?;
+            logic::remote_fn_impl(attrs, f, &package, source, out_dir)
+        })
+        .map(track_env)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+#[proc_macro_attribute]
+pub fn remote_impl(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let attrs = parse_macro_input!(attr as logic::GwasmAttrs);
+    let item = parse_macro_input!(item as syn::Item);
+    PackageConfig::load()
+        .and_then(|package| {
+            let source = Source::locate();
+            let out_dir = out_dir(&source)?;
+            logic::remote_impl_impl(attrs, item, &package, source, out_dir)
+        })
+        .map(track_env)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+#[proc_macro_attribute]
+pub fn remote_mod(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let attrs = parse_macro_input!(attr as logic::GwasmAttrs);
+    let item = parse_macro_input!(item as syn::ItemMod);
+    PackageConfig::load()
+        .and_then(|package| {
+            let source = Source::locate();
+            let out_dir = out_dir(&source)?;
+            logic::remote_mod_impl(attrs, item, &package, source, out_dir)
+        })
+        .map(track_env)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_manifest() {
+        let tracked = track_env(quote!()).to_string();
+        assert!(
+            tracked.contains(
+                "include_bytes ! (concat ! (env ! (\"CARGO_MANIFEST_DIR\") , \"/Cargo.toml\"))"
+            ),
+            "{}",
+            tracked
+        );
+    }
 }
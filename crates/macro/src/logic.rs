@@ -1,19 +1,38 @@
-use proc_macro2::TokenStream;
-use quote::{format_ident, quote};
-use std::{collections::VecDeque, env, fs::File, io::Write, path::Path};
+use crate::{
+    package::PackageConfig,
+    source::{self, Source},
+};
+use proc_macro2::{Delimiter, Group, Span, TokenStream, TokenTree};
+use quote::{format_ident, quote, ToTokens};
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    str::FromStr,
+};
 use syn::{
     parenthesized,
     parse::{Parse, ParseStream},
+    parse_quote,
     punctuated::Punctuated,
     token::Paren,
-    Block, ExprLit, FnArg, Ident, Lit, Pat, ReturnType, Token, Type, Visibility,
+    Attribute, Block, ExprLit, FnArg, GenericArgument, Generics, Ident, ImplItem, Item, ItemEnum,
+    ItemMod, ItemStruct, ItemUse, Lit, LitStr, Pat, PathArguments, PathSegment, ReturnType, Token,
+    Type, TypeParamBound, UseGroup, UseName, UsePath, UseRename, UseTree, Visibility,
 };
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct GwasmFn {
+    attrs: Vec<Attribute>,
     vis: Visibility,
+    constness: Option<Token![const]>,
+    unsafety: Option<Token![unsafe]>,
     fn_token: Token![fn],
     ident: Ident,
+    generics: Generics,
     paren_token: Paren,
     args: Punctuated<FnArg, Token![,]>,
     ret: ReturnType,
@@ -23,55 +42,291 @@ pub struct GwasmFn {
 impl Parse for GwasmFn {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let content;
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        let constness = input.parse()?;
+        let unsafety = input.parse()?;
+        let fn_token = input.parse()?;
+        let ident = input.parse()?;
+        let mut generics: Generics = input.parse()?;
+        let paren_token = parenthesized!(content in input);
+        let args = content.parse_terminated(FnArg::parse)?;
+        let ret = input.parse()?;
+        generics.where_clause = input.parse()?;
         Ok(GwasmFn {
-            vis: input.parse()?,
-            fn_token: input.parse()?,
-            ident: input.parse()?,
-            paren_token: parenthesized!(content in input),
-            args: content.parse_terminated(FnArg::parse)?,
-            ret: input.parse()?,
+            attrs,
+            vis,
+            constness,
+            unsafety,
+            fn_token,
+            ident,
+            generics,
+            paren_token,
+            args,
+            ret,
             body: input.parse()?,
         })
     }
 }
 
-fn validate_extract_args(input: impl IntoIterator<Item = FnArg>) -> Vec<(Box<Pat>, Box<Type>)> {
+/// How a value crosses the boundary between the host and the Wasm module.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    /// Serialized with the codec.
+    Value,
+    /// `Vec<u8>` marked with `#[raw]`, passed verbatim.
+    Raw,
+    /// `gfaas::RemoteFile`, uploaded as is.
+    File,
+    /// `gfaas::RemoteDir`, uploaded as is.
+    Dir,
+}
+
+impl Kind {
+    fn of(ty: &Type, raw: bool) -> Self {
+        if raw {
+            return Kind::Raw;
+        }
+        match last_segment(ty) {
+            Some(segment) if segment.ident == "RemoteFile" => Kind::File,
+            Some(segment) if segment.ident == "RemoteDir" => Kind::Dir,
+            _ => Kind::Value,
+        }
+    }
+}
+
+/// Argument of the function as (pattern, type, kind) triple.
+type Arg = (Box<Pat>, Box<Type>, Kind);
+
+/// Extracts the arguments, and strips the `#[raw]` markers off `input`.
+fn validate_extract_args<'a>(
+    input: impl IntoIterator<Item = &'a mut FnArg>,
+) -> syn::Result<Vec<Arg>> {
     let mut args = vec![];
     for arg in input {
-        let (pat, ty) = match arg {
-            FnArg::Typed(arg) => {
-                if arg.attrs.len() > 0 {
-                    panic!("attributes around function arguments are unsupported");
+        let (pat, ty, kind) =
+            match arg {
+                FnArg::Typed(arg) => {
+                    let raw = extract_marker(&mut arg.attrs, "raw");
+                    if let Some(attr) = arg.attrs.first() {
+                        return Err(syn::Error::new_spanned(
+                        attr,
+                        "attributes around function arguments other than '#[raw]' are unsupported",
+                    ));
+                    }
+                    if raw && !is_byte_vec(&arg.ty) {
+                        return Err(syn::Error::new_spanned(
+                            &arg.ty,
+                            "'#[raw]' arguments have to be of type 'Vec<u8>'",
+                        ));
+                    }
+                    (arg.pat.clone(), arg.ty.clone(), Kind::of(&arg.ty, raw))
                 }
-                let pat = arg.pat;
-                let ty = arg.ty;
-                (pat, ty)
-            }
-            _ => panic!("functions taking 'self' are unsupported"),
-        };
-        args.push((pat, ty));
+                FnArg::Receiver(receiver) => return Err(syn::Error::new_spanned(
+                    receiver,
+                    "functions taking 'self' are unsupported by 'remote_fn': annotate the whole \
+                     impl block with 'remote_impl' instead",
+                )),
+            };
+        args.push((pat, ty, kind));
+    }
+    Ok(args)
+}
+
+/// Returns the name of the argument bound to `pat`, i.e., without `mut`, so that it can be
+/// forwarded to another function.
+fn arg_name(pat: &Pat) -> TokenStream {
+    match pat {
+        Pat::Ident(pat) => pat.ident.to_token_stream(),
+        pat => pat.to_token_stream(),
+    }
+}
+
+/// How a method takes `self`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Receiver {
+    /// `&self`
+    Ref,
+    /// `self`
+    Value,
+}
+
+impl Receiver {
+    fn of(receiver: &syn::Receiver) -> syn::Result<Self> {
+        match (&receiver.reference, &receiver.mutability) {
+            (Some(_), Some(_)) => Err(syn::Error::new_spanned(
+                receiver,
+                "methods taking '&mut self' are unsupported: the Wasm module only gets a copy \
+                 of the receiver, so return the modified value instead",
+            )),
+            (Some(_), None) => Ok(Receiver::Ref),
+            (None, _) => Ok(Receiver::Value),
+        }
+    }
+}
+
+/// Removes the marker attribute `name`, e.g., `#[raw]`, from `attrs`, and returns whether it
+/// was there.
+fn extract_marker(attrs: &mut Vec<Attribute>, name: &str) -> bool {
+    let len = attrs.len();
+    attrs.retain(|attr| !attr.path.is_ident(name));
+    attrs.len() != len
+}
+
+/// Returns the last segment of the path if `ty` is a path, e.g., `RemoteFile` in case of
+/// `gfaas::RemoteFile`.
+fn last_segment(ty: &Type) -> Option<&PathSegment> {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last(),
+        _ => None,
+    }
+}
+
+/// Returns `true` if `ty` looks like `Vec<u8>`.
+fn is_byte_vec(ty: &Type) -> bool {
+    let segment = match last_segment(ty) {
+        Some(segment) if segment.ident == "Vec" => segment,
+        _ => return false,
+    };
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(Type::Path(elem)) => elem.path.is_ident("u8"),
+            _ => false,
+        },
+        _ => false,
     }
-    args
 }
 
-fn validate_extract_return_type(output: &ReturnType) -> Box<Type> {
+/// Returns the output type of the function, where no return type at all means `()`.
+fn extract_return_type(output: &ReturnType) -> Box<Type> {
     match output {
-        ReturnType::Default => panic!("functions returning unit type () are unsupported"),
+        ReturnType::Default => parse_quote!(()),
         ReturnType::Type(_, tt) => tt.clone(),
     }
 }
 
+/// If `ty` looks like `Result<T, E>`, returns `T` and `E`.
+fn extract_result_types(ty: &Type) -> Option<(&Type, &Type)> {
+    let segment = last_segment(ty)?;
+    if segment.ident != "Result" {
+        return None;
+    }
+    let args = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => &args.args,
+        _ => return None,
+    };
+    match (args.len(), args.first(), args.last()) {
+        (2, Some(GenericArgument::Type(ok)), Some(GenericArgument::Type(err))) => Some((ok, err)),
+        _ => None,
+    }
+}
+
+/// If `ty` looks like `impl Iterator<Item = T>`, returns `T`.
+fn extract_iterator_item(ty: &Type) -> Option<&Type> {
+    let bounds = match ty {
+        Type::ImplTrait(ty) => &ty.bounds,
+        _ => return None,
+    };
+    bounds.iter().find_map(|bound| {
+        let segment = match bound {
+            TypeParamBound::Trait(bound) => bound.path.segments.last()?,
+            _ => return None,
+        };
+        if segment.ident != "Iterator" {
+            return None;
+        }
+        match &segment.arguments {
+            PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+                GenericArgument::Binding(binding) if binding.ident == "Item" => Some(&binding.ty),
+                _ => None,
+            }),
+            _ => None,
+        }
+    })
+}
+
+/// Returns `true` if `ty` mentions any of `idents`, e.g., `Vec<T>` mentions `T`.
+fn mentions_any(ty: &Type, idents: &[Ident]) -> bool {
+    fn walk(tokens: TokenStream, idents: &[Ident]) -> bool {
+        tokens.into_iter().any(|tt| match tt {
+            TokenTree::Ident(ident) => idents.contains(&ident),
+            TokenTree::Group(group) => walk(group.stream(), idents),
+            _ => false,
+        })
+    }
+    walk(quote!(#ty), idents)
+}
+
+/// Name of the Wasm module of the instantiation of the function `ident` with `types`, e.g.,
+/// `scale_f64` for `scale::<f64>`.
+fn instance_name(ident: &Ident, types: &[Type]) -> String {
+    let mut name = ident.to_string();
+    for ty in types {
+        let ty = quote!(#ty).to_string();
+        for word in ty.split(|c: char| !c.is_ascii_alphanumeric()) {
+            if !word.is_empty() {
+                name.push('_');
+                name.push_str(word);
+            }
+        }
+    }
+    name
+}
+
+#[allow(dead_code)]
 #[derive(Debug)]
-pub struct GwasmAttr {
-    ident: Ident,
-    eq_token: Token![=],
-    value: ExprLit,
+pub enum GwasmAttr {
+    /// `ident = value`
+    Value {
+        ident: Ident,
+        eq_token: Token![=],
+        value: ExprLit,
+    },
+    /// `ident(type, ...)`
+    Types {
+        ident: Ident,
+        paren_token: Paren,
+        types: Punctuated<Type, Token![,]>,
+    },
+}
+
+impl ToTokens for GwasmAttr {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            GwasmAttr::Value {
+                ident,
+                eq_token,
+                value,
+            } => {
+                ident.to_tokens(tokens);
+                eq_token.to_tokens(tokens);
+                value.to_tokens(tokens);
+            }
+            GwasmAttr::Types {
+                ident,
+                paren_token,
+                types,
+            } => {
+                ident.to_tokens(tokens);
+                paren_token.surround(tokens, |tokens| types.to_tokens(tokens));
+            }
+        }
+    }
 }
 
 impl Parse for GwasmAttr {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        Ok(GwasmAttr {
-            ident: input.parse()?,
+        let ident = input.parse()?;
+        if input.peek(Paren) {
+            let content;
+            return Ok(GwasmAttr::Types {
+                ident,
+                paren_token: parenthesized!(content in input),
+                types: content.parse_terminated(Type::parse)?,
+            });
+        }
+        Ok(GwasmAttr::Value {
+            ident,
             eq_token: input.parse()?,
             value: input.parse()?,
         })
@@ -90,292 +345,2469 @@ impl Parse for GwasmAttrs {
 #[derive(Debug, Default)]
 struct GwasmParams {
     run_local: Option<bool>,
+    native: Option<bool>,
+    backend: Option<syn::Path>,
     budget: Option<u64>,
     timeout: Option<u64>, // In seconds. TODO figure out a more user-friendly alts.
     subnet: Option<String>,
+    retries: Option<u32>,
+    backoff: Option<TokenStream>,
+    codec: Option<String>,
+    group: Option<LitStr>,
+    instantiate: Vec<Type>,
+    min_mem_gib: Option<f64>,
+    min_storage_gib: Option<f64>,
+    min_cpu_threads: Option<u32>,
+    constraints: Option<String>,
+    remote_mod: Option<String>,
 }
 
-pub(super) fn remote_fn_impl(attrs: GwasmAttrs, f: GwasmFn, preserved: TokenStream) -> TokenStream {
-    // Parse attributes
-    let mut params = GwasmParams::default();
-    for attr in attrs.0.into_iter() {
-        let attr_str = attr.ident.to_string();
-        match attr_str.as_str() {
-            "run_local" => {
-                let lit = attr.value.lit;
-                match lit {
-                    Lit::Str(s) => params
-                        .run_local
-                        .replace(s.value().parse().expect("correct value")),
-                    Lit::Bool(b) => params.run_local.replace(b.value),
-                    x => panic!(
-                        "invalid attribute value '{:#?}': expected string or bool",
-                        x
-                    ),
-                };
-            }
-            "budget" => {
-                let lit = attr.value.lit;
-                match lit {
-                    Lit::Str(s) => params
-                        .budget
-                        .replace(s.value().parse().expect("correct value")),
-                    Lit::Int(i) => params
-                        .budget
-                        .replace(i.base10_parse().expect("correct value")),
-                    x => panic!("invalid attribute value '{:#?}': expected string or int", x),
-                };
-            }
-            "timeout" => {
-                let lit = attr.value.lit;
-                match lit {
-                    Lit::Str(s) => params
-                        .timeout
-                        .replace(s.value().parse().expect("correct value")),
-                    Lit::Int(i) => params
-                        .timeout
-                        .replace(i.base10_parse().expect("correct value")),
-                    x => panic!("invalid attribute value '{:#?}': expected string or int", x),
-                };
-            }
-            "subnet" => {
-                let lit = attr.value.lit;
-                match lit {
-                    Lit::Str(s) => params.subnet.replace(s.value()),
-                    x => panic!("invalid attribute value '{:#?}': expected string or int", x),
-                };
-            }
-            x => panic!(
-                "unexpected attribute '{}': expected 'budget', 'timeout', or 'subnet'",
-                x
-            ),
+/// Parses the value of a bool attribute, given either as a bool or a string.
+fn parse_bool(lit: &Lit) -> syn::Result<bool> {
+    match lit {
+        Lit::Bool(b) => Ok(b.value),
+        Lit::Str(s) => s
+            .value()
+            .parse()
+            .map_err(|_| syn::Error::new_spanned(s, "invalid value: expected 'true' or 'false'")),
+        x => Err(syn::Error::new_spanned(
+            x,
+            "invalid attribute value: expected string or bool",
+        )),
+    }
+}
+
+/// Parses the value of a numeric attribute, given either as an int or a string.
+fn parse_int<N>(lit: &Lit) -> syn::Result<N>
+where
+    N: FromStr,
+    N::Err: Display,
+{
+    match lit {
+        Lit::Int(i) => i.base10_parse(),
+        Lit::Str(s) => s.value().parse().map_err(|err| {
+            syn::Error::new_spanned(s, format!("invalid value: expected an integer ({})", err))
+        }),
+        x => Err(syn::Error::new_spanned(
+            x,
+            "invalid attribute value: expected string or int",
+        )),
+    }
+}
+
+/// Parses the value of a non-negative numeric attribute, given either as a float, an int or
+/// a string.
+fn parse_float(lit: &Lit) -> syn::Result<f64> {
+    let value = match lit {
+        Lit::Float(f) => f.base10_parse()?,
+        Lit::Int(i) => i.base10_parse()?,
+        Lit::Str(s) => s.value().parse().map_err(|err| {
+            syn::Error::new_spanned(s, format!("invalid value: expected a number ({})", err))
+        })?,
+        x => {
+            return Err(syn::Error::new_spanned(
+                x,
+                "invalid attribute value: expected string, float or int",
+            ))
         }
+    };
+    if !(value >= 0.0 && f64::is_finite(value)) {
+        return Err(syn::Error::new_spanned(
+            lit,
+            "invalid value: expected a non-negative number",
+        ));
     }
+    Ok(value)
+}
 
-    // Validate and extract arguments
-    let args = validate_extract_args(f.args.iter().map(|x| x.clone()));
-    // Expand into gWasm connector code
-    let fn_vis = f.vis;
-    let fn_ident = f.ident;
-    let fn_args = f.args;
-    let return_type = validate_extract_return_type(&f.ret);
-
-    let run_local = params.run_local.unwrap_or(false);
-    let budget = params.budget.unwrap_or(100);
-    let timeout = params.timeout.unwrap_or(10 * 60);
-    let subnet = params.subnet.unwrap_or("devnet-alpha.2".to_string());
-
-    let mut local_input_args = vec![];
-    let mut remote_input_args = vec![];
-    let input_file_names: Vec<_> = (0..args.len()).map(|i| format!("in{}", i)).collect();
-    for (name, (arg, _)) in input_file_names.iter().zip(args.iter()) {
-        let ts = quote! {
-            let input_path = vol.join(#name);
-            let serialized = serde_json::to_vec(&#arg).context("serializing input data")?;
-            fs::write(&input_path, serialized).context("writing serialized data to file")?;
-        };
-        local_input_args.push(ts);
+/// Parses the value of a string attribute.
+fn parse_str(lit: &Lit) -> syn::Result<&LitStr> {
+    match lit {
+        Lit::Str(s) => Ok(s),
+        x => Err(syn::Error::new_spanned(
+            x,
+            "invalid attribute value: expected string",
+        )),
+    }
+}
 
-        let ts = quote! {
-            let input_path = workspace.path().join(#name);
-            let serialized = serde_json::to_vec(&#arg).context("serializing input data")?;
-            fs::write(&input_path, serialized).context("writing serialized data to file")?;
+impl GwasmParams {
+    /// Parses the arguments to `remote_fn` or `remote_impl`, falling back to the settings of
+    /// the `package` for the ones not given.
+    fn parse(attrs: GwasmAttrs, package: &PackageConfig) -> syn::Result<Self> {
+        let mut params = GwasmParams {
+            budget: package.budget,
+            subnet: package.subnet.clone(),
+            codec: package.codec.clone(),
+            ..GwasmParams::default()
         };
-        remote_input_args.push(ts);
+        for attr in attrs.0.into_iter() {
+            let (ident, value) = match attr {
+                GwasmAttr::Value { ident, value, .. } => (ident, value),
+                GwasmAttr::Types { ident, types, .. } => {
+                    if ident != "instantiate" {
+                        return Err(syn::Error::new_spanned(
+                            ident,
+                            "unexpected attribute: expected 'instantiate(...)'",
+                        ));
+                    }
+                    params.instantiate.extend(types);
+                    continue;
+                }
+            };
+            let lit = &value.lit;
+            match ident.to_string().as_str() {
+                "run_local" => {
+                    params.run_local.replace(parse_bool(lit)?);
+                }
+                "native" => {
+                    params.native.replace(parse_bool(lit)?);
+                }
+                "backend" => {
+                    let s = parse_str(lit)?;
+                    params.backend.replace(match s.value().as_str() {
+                        "embedded" => syn::parse_quote!(gfaas::backend::Embedded),
+                        "local" => syn::parse_quote!(gfaas::backend::Local),
+                        "yagna" => syn::parse_quote!(gfaas::backend::Yagna),
+                        "mock" => syn::parse_quote!(gfaas::backend::Mock),
+                        _ => s.parse().map_err(|_| {
+                            syn::Error::new_spanned(
+                                s,
+                                "invalid backend: expected 'embedded', 'local', 'yagna', 'mock', \
+                                 or a path to a type implementing 'gfaas::Backend'",
+                            )
+                        })?,
+                    });
+                }
+                "budget" => {
+                    params.budget.replace(parse_int(lit)?);
+                }
+                "timeout" => {
+                    params.timeout.replace(parse_int(lit)?);
+                }
+                "subnet" => {
+                    params.subnet.replace(parse_str(lit)?.value());
+                }
+                "retries" => {
+                    params.retries.replace(parse_int(lit)?);
+                }
+                "backoff" => {
+                    let s = parse_str(lit)?;
+                    params.backoff.replace(match s.value().as_str() {
+                        "none" => quote!(gfaas::Backoff::None),
+                        "constant" => quote!(gfaas::Backoff::default()),
+                        "exponential" => quote!(gfaas::Backoff::exponential()),
+                        _ => {
+                            return Err(syn::Error::new_spanned(
+                                s,
+                                "invalid backoff: expected 'none', 'constant', or 'exponential'",
+                            ))
+                        }
+                    });
+                }
+                "codec" => {
+                    let s = parse_str(lit)?;
+                    match s.value().as_str() {
+                        x @ "json" | x @ "bincode" | x @ "cbor" | x @ "msgpack" => {
+                            params.codec.replace(x.to_owned());
+                        }
+                        _ => {
+                            return Err(syn::Error::new_spanned(
+                                s,
+                                "invalid codec: expected 'json', 'bincode', 'cbor', or 'msgpack'",
+                            ))
+                        }
+                    }
+                }
+                "group" => {
+                    let s = parse_str(lit)?;
+                    let group = s.value();
+                    let valid = group.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                    if group.is_empty() || !valid {
+                        return Err(syn::Error::new_spanned(
+                            s,
+                            "invalid group: expected a name made of alphanumeric characters \
+                             and underscores",
+                        ));
+                    }
+                    params.group.replace(s.clone());
+                }
+                "min_mem_gib" => {
+                    params.min_mem_gib.replace(parse_float(lit)?);
+                }
+                "min_storage_gib" => {
+                    params.min_storage_gib.replace(parse_float(lit)?);
+                }
+                "min_cpu_threads" => {
+                    let threads = parse_int(lit)?;
+                    if threads == 0 {
+                        return Err(syn::Error::new_spanned(
+                            lit,
+                            "invalid value: expected a positive integer",
+                        ));
+                    }
+                    params.min_cpu_threads.replace(threads);
+                }
+                // Added by `remote_mod` to the functions of the module, as the name of its copy.
+                "__remote_mod" => {
+                    params.remote_mod.replace(parse_str(lit)?.value());
+                }
+                "constraints" => {
+                    let s = parse_str(lit)?;
+                    gfaas_constraints::parse(&s.value()).map_err(|err| {
+                        syn::Error::new_spanned(s, format!("invalid constraints: {}", err))
+                    })?;
+                    params.constraints.replace(s.value());
+                }
+                _ => return Err(syn::Error::new_spanned(
+                    ident,
+                    "unexpected attribute: expected 'backend', 'run_local', 'native', 'budget', \
+                     'timeout', 'subnet', 'retries', 'backoff', 'codec', 'group', \
+                     'min_mem_gib', 'min_storage_gib', 'min_cpu_threads', 'constraints', or \
+                     'instantiate(...)'",
+                )),
+            }
+        }
+        Ok(params)
     }
 
-    let mut local_input_paths = vec![];
-    let mut remote_input_paths = vec![];
-    let mut remote_input_names = vec![];
-    for name in input_file_names {
-        let ts = quote! {
-            ["/workdir/", #name].join(""),
-        };
-        local_input_paths.push(ts);
+    /// Whether the function runs natively, as requested with the `native` argument, or for all
+    /// of the functions with the `native` feature.
+    fn native(&self) -> bool {
+        self.native.unwrap_or(cfg!(feature = "native"))
+    }
 
-        let ts = quote! {
-            format!("/workdir/{}", #name)
-        };
-        remote_input_names.push(ts);
+    /// Backend selected with the `backend` or `run_local` arguments.
+    fn backend(&self) -> TokenStream {
+        match (&self.backend, self.run_local.unwrap_or(false)) {
+            (Some(backend), _) => quote!(#backend),
+            (None, true) => quote!(gfaas::backend::Local),
+            (None, false) => quote!(gfaas::backend::Yagna),
+        }
+    }
+
+    /// Statements overriding the default `config` with the Golem's configuration parameters.
+    fn config(&self) -> Vec<TokenStream> {
+        let mut config = vec![];
+        if let Some(budget) = self.budget {
+            config.push(quote!(config.budget = #budget;));
+        }
+        if let Some(timeout) = self.timeout {
+            config.push(quote!(config.timeout = std::time::Duration::from_secs(#timeout);));
+        }
+        if let Some(subnet) = &self.subnet {
+            config.push(quote!(config.subnet = #subnet.to_owned();));
+        }
+        if let Some(retries) = self.retries {
+            config.push(quote!(config.retry.retries = #retries;));
+        }
+        if let Some(backoff) = &self.backoff {
+            config.push(quote!(config.retry.backoff = #backoff;));
+        }
+        if let Some(gib) = self.min_mem_gib {
+            config.push(quote!(config.min_mem_gib = #gib;));
+        }
+        if let Some(gib) = self.min_storage_gib {
+            config.push(quote!(config.min_storage_gib = #gib;));
+        }
+        if let Some(threads) = self.min_cpu_threads {
+            config.push(quote!(config.min_cpu_threads = Some(#threads);));
+        }
+        if let Some(constraints) = &self.constraints {
+            config.push(quote!(config.constraints.push(#constraints.to_owned());));
+        }
+        config
+    }
+}
+
+/// Codec used on the host side, and the matching functions used by the Wasm module.
+struct Codec {
+    name: String,
+    host: TokenStream,
+    guest_encode: TokenStream,
+    guest_decode: TokenStream,
+}
 
-        let ts = quote! {
-            upload(workspace.path().join(#name), format!("/workdir/{}", #name));
+impl Codec {
+    fn new(name: Option<&str>) -> Self {
+        let name = name.unwrap_or("json").to_owned();
+        let (host, guest_encode, guest_decode) = match name.as_str() {
+            "bincode" => (
+                quote!(gfaas::codec::Bincode),
+                quote!(bincode::serialize),
+                quote!(bincode::deserialize),
+            ),
+            "cbor" => (
+                quote!(gfaas::codec::Cbor),
+                quote!(serde_cbor::to_vec),
+                quote!(serde_cbor::from_slice),
+            ),
+            "msgpack" => (
+                quote!(gfaas::codec::MessagePack),
+                quote!(rmp_serde::to_vec),
+                quote!(rmp_serde::from_slice),
+            ),
+            _ => (
+                quote!(gfaas::codec::Json),
+                quote!(serde_json::to_vec),
+                quote!(serde_json::from_slice),
+            ),
         };
-        remote_input_paths.push(ts);
+        Self {
+            name,
+            host,
+            guest_encode,
+            guest_decode,
+        }
     }
 
-    let output = {
-        if run_local {
-            quote! {
-                #fn_vis async fn #fn_ident(#fn_args) -> std::result::Result<#return_type, gfaas::Error> {
-                    use gfaas::__private::anyhow::{anyhow, Context};
-                    use gfaas::__private::tokio::task;
-                    use gfaas::__private::tempfile::tempdir;
-                    use gfaas::__private::ya_runtime_wasi;
-                    use gfaas::__private::package::Package;
-                    use gfaas::__private::serde_json;
-                    use std::{fs, env, path::PathBuf};
+    /// Serializes `value` on the host. Only values go through the codec, the rest is passed
+    /// verbatim.
+    fn encode(&self, kind: Kind, value: TokenStream, context: &str) -> TokenStream {
+        let codec = &self.host;
+        match kind {
+            Kind::Value => quote! {
+                <#codec as gfaas::Codec>::encode(&#value)
+                    .context(#context)
+                    .map_err(gfaas::Error::Serialization)?
+            },
+            _ => value,
+        }
+    }
+
+    /// Deserializes `data` on the host.
+    fn decode(&self, kind: Kind, data: TokenStream, context: &str) -> TokenStream {
+        let codec = &self.host;
+        match kind {
+            Kind::Value => quote! {
+                <#codec as gfaas::Codec>::decode(&#data)
+                    .context(#context)
+                    .map_err(gfaas::Error::Serialization)?
+            },
+            _ => data,
+        }
+    }
+
+    /// Hands `value` over to the backend: files and directories as paths, the rest as data.
+    fn input(&self, kind: Kind, value: TokenStream) -> TokenStream {
+        match kind {
+            Kind::File => quote!(gfaas::backend::Input::File(#value.into_path_buf())),
+            Kind::Dir => quote!(gfaas::backend::Input::Dir(#value.into_path_buf())),
+            _ => {
+                let data = self.encode(kind, value, "serializing input data");
+                quote!(gfaas::backend::Input::Data(#data))
+            }
+        }
+    }
+
+    /// Statements of the Wasm module reading the inputs into `in_idents`. The inputs are
+    /// popped off the end of the args, so in reverse order.
+    fn guest_inputs(&self, args: &[Arg], in_idents: &[Ident]) -> Vec<TokenStream> {
+        let guest_decode = &self.guest_decode;
+        let mut inputs = vec![];
+        for ((_, _, kind), in_ident) in args.iter().zip(in_idents).rev() {
+            let read = match kind {
+                Kind::Value => quote! {
+                    let #in_ident = fs::read(next_arg).unwrap();
+                    let #in_ident = #guest_decode(&#in_ident).unwrap();
+                },
+                Kind::Raw => quote!(let #in_ident = fs::read(next_arg).unwrap();),
+                Kind::File => quote!(let #in_ident = gfaas::RemoteFile::new(next_arg);),
+                // Empty directories are not uploaded, so make sure it exists.
+                Kind::Dir => quote! {
+                    fs::create_dir_all(&next_arg).unwrap();
+                    let #in_ident = gfaas::RemoteDir::new(next_arg);
+                },
+            };
+            inputs.push(quote! {
+                let next_arg = args.pop().unwrap();
+                #read
+            });
+        }
+        inputs
+    }
+}
+
+/// Output of the function.
+struct Output {
+    return_type: Box<Type>,
+    /// Functions returning `impl Iterator<Item = T>` produce a stream of items, each of which
+    /// is treated just like an output of the function would otherwise be.
+    stream_item: Option<Type>,
+    kind: Kind,
+}
+
+impl Output {
+    fn new(ret: &ReturnType, raw: bool) -> syn::Result<Self> {
+        let return_type = extract_return_type(ret);
+        let stream_item = extract_iterator_item(&return_type).cloned();
+        let output_type = stream_item.as_ref().unwrap_or(&return_type);
+        if raw && !is_byte_vec(output_type) {
+            return Err(syn::Error::new_spanned(
+                output_type,
+                "'#[raw]' functions have to return 'Vec<u8>' or 'impl Iterator<Item = Vec<u8>>'",
+            ));
+        }
+        let kind = Kind::of(output_type, raw);
+        if kind == Kind::Dir {
+            return Err(syn::Error::new_spanned(
+                output_type,
+                "functions returning 'RemoteDir' are unsupported: return a 'RemoteFile', e.g., \
+                 an archive of the directory, instead",
+            ));
+        }
+        if kind == Kind::File && stream_item.is_some() {
+            return Err(syn::Error::new_spanned(
+                output_type,
+                "functions returning iterators of 'RemoteFile' are unsupported",
+            ));
+        }
+        Ok(Self {
+            return_type,
+            stream_item,
+            kind,
+        })
+    }
+
+    /// The type of the output, or of its items in case of a stream.
+    fn ty(&self) -> &Type {
+        self.stream_item.as_ref().unwrap_or(&self.return_type)
+    }
+
+    /// The type the host gets back in place of the function's output.
+    fn host_type(&self) -> TokenStream {
+        match &self.stream_item {
+            Some(item) => quote!(gfaas::Stream<#item>),
+            None => {
+                let return_type = &self.return_type;
+                quote!(#return_type)
+            }
+        }
+    }
+
+    /// Functions returning `Result<T, E>` are flattened into `Result<T, RemoteError<E>>` so
+    /// that the error returned by the function can be told apart from the failure to run it.
+    /// The `map` companion unflattens them back, returning `Result<T, E>` for every input.
+    ///
+    /// Returns the return type of the expanded function, and the expressions turning `res`
+    /// into it, and back.
+    fn flatten(&self) -> (TokenStream, TokenStream, TokenStream) {
+        match extract_result_types(&self.return_type) {
+            Some((ok, err)) => (
+                quote!(std::result::Result<#ok, gfaas::RemoteError<#err>>),
+                quote!(res.map_err(gfaas::RemoteError::Remote)),
+                quote! {
+                    match res {
+                        Ok(res) => Ok(Ok(res)),
+                        Err(gfaas::RemoteError::Remote(err)) => Ok(Err(err)),
+                        Err(gfaas::RemoteError::Dispatch(err)) => Err(err),
+                    }
+                },
+            ),
+            None => {
+                let host_type = self.host_type();
+                (
+                    quote!(std::result::Result<#host_type, gfaas::Error>),
+                    quote!(Ok(res)),
+                    quote!(res),
+                )
+            }
+        }
+    }
+
+    /// Turns the `output_data` downloaded from the backend into the host type.
+    fn decoded(&self, codec: &Codec) -> TokenStream {
+        match (&self.stream_item, self.kind) {
+            (Some(item), kind) => {
+                let decoded = codec.decode(kind, quote!(data), "deserializing output data");
+                quote! {
+                    gfaas::__private::from_records(
+                        output_data,
+                        |data: Vec<u8>| -> std::result::Result<#item, gfaas::Error> { Ok(#decoded) },
+                    )
+                }
+            }
+            (None, Kind::File) => quote!(gfaas::RemoteFile::from_output(output_data)?),
+            (None, kind) => codec.decode(kind, quote!(output_data), "deserializing output data"),
+        }
+    }
+
+    /// Statements running the function natively with `call`, leaving the host type in `res`.
+    fn native_run(&self, codec: &Codec, call: TokenStream) -> TokenStream {
+        let host_type = self.host_type();
+        let encoded_output = codec.encode(self.kind, quote!(res), "serializing output data");
+        match &self.stream_item {
+            // The items are sent over as they are produced, so that the host can consume them
+            // while the function is still running.
+            Some(item) => {
+                let decoded = codec.decode(self.kind, quote!(data), "deserializing output data");
+                quote! {
+                    use gfaas::__private::futures::{channel::mpsc, executor::block_on, sink::SinkExt};
 
+                    let (mut tx, rx) = mpsc::channel(16);
                     task::spawn_blocking(move || {
-                        // 0. Create temp workspace
-                        let workspace = tempdir().context("creating temp dir")?;
-
-                        // 1. Prepare zip archive
-                        let exe_path = env::current_exe().context("extracting path to the current exe")?;
-                        let parent = exe_path
-                            .parent()
-                            .ok_or_else(|| anyhow!("path to the current exe without parent: '{}'", exe_path.display()))?;
-                        let module_name = format!("{}", stringify!(#fn_ident));
-                        let wasm = parent.join(format!("{}.wasm", module_name));
-                        let package_path = workspace.path().join("pkg.zip");
-                        let mut package = Package::new();
-                        package.add_module_from_path(wasm).context("adding Wasm module from path")?;
-                        package.write(&package_path).context("saving Yagna zip package to file")?;
-
-                        // 2. Deploy
-                        ya_runtime_wasi::deploy(workspace.path(), &package_path).context("deploying Yagna package")?;
-                        ya_runtime_wasi::start(workspace.path()).context("executing Yagna start command")?;
-
-                        let deployment = ya_runtime_wasi::DeployFile::load(workspace.path()).context("loading deployed Yagna package")?;
-                        let vol = deployment
-                            .vols()
-                            .find(|vol| vol.path.starts_with("/workdir"))
-                            .map(|vol| workspace.path().join(&vol.name))
-                            .context("extracting workdir path from Yagna package")?;
-
-                        let output_file_name = "out".to_owned();
-                        let output_path = vol.join(&output_file_name);
-
-                        #(#local_input_args)*
-
-                        // 3. Run
-                        ya_runtime_wasi::run(
-                            workspace.path(),
-                            &module_name,
-                            vec![
-                                #(#local_input_paths)*
-                                ["/workdir/", &output_file_name].join(""),
-                            ],
-                        ).context("executing Yagna run command")?;
-
-                        // 4. Collect the results
-                        let output_data = fs::read(output_path).context("reading output data from file")?;
-                        let res = serde_json::from_slice(&output_data).context("deserializing output data")?;
-                        Ok(res)
-                    }).await?
+                        let res = gfaas::__private::catch_panic(
+                            || -> std::result::Result<(), gfaas::Error> {
+                                for res in #call {
+                                    if block_on(tx.send(Ok(#encoded_output))).is_err() {
+                                        // The stream was dropped, so nobody is interested in
+                                        // the rest of the items.
+                                        break;
+                                    }
+                                }
+                                Ok(())
+                            },
+                        );
+                        if let Err(err) = res.and_then(|res| res) {
+                            let _ = block_on(tx.send(Err(err)));
+                        }
+                    });
+                    let res: #host_type = gfaas::__private::from_channel(
+                        rx,
+                        |data: Vec<u8>| -> std::result::Result<#item, gfaas::Error> { Ok(#decoded) },
+                    );
                 }
             }
-        } else {
-            quote! {
-                #fn_vis async fn #fn_ident(#fn_args) -> std::result::Result<#return_type, gfaas::Error> {
-                    use gfaas::__private::anyhow::{self, Context, anyhow};
-                    use gfaas::__private::futures::future::{select, FutureExt};
-                    use gfaas::__private::tempfile::tempdir;
-                    use gfaas::__private::package::Package;
-                    use gfaas::__private::yarapi::{commands, requestor::{self, CommandList, Image::Wasm, Requestor}};
-                    use gfaas::__private::ya_agreement_utils::{constraints, ConstraintKey, Constraints};
-                    use gfaas::__private::serde_json;
-                    use std::{fs, env, path::{Path, PathBuf}, collections::HashMap};
-
-                    // 1. Create temp workspace
-                    let workspace = tempdir().context("creating temp dir")?;
-
-                    // 2. Prepare package
-                    let exe_path = env::current_exe().context("extracting path to the current exe")?;
-                    let parent = exe_path
-                        .parent()
-                        .ok_or_else(|| anyhow!("path to the current exe without parent: '{}'", exe_path.display()))?;
-                    let module_name = format!("{}", stringify!(#fn_ident));
-                    let wasm = parent.join(format!("{}.wasm", module_name));
-                    let package_path = workspace.path().join("pkg.zip");
-                    let mut package = Package::new();
-                    package.add_module_from_path(wasm).context("adding Wasm module from path")?;
-                    package.write(&package_path).context("saving Yagna zig package to file")?;
-
-                    // 3. Prepare workspace
-                    let output_path = workspace.path().join("out");
-
-                    #(#remote_input_args)*
-
-                    // 4. Run
-                    Requestor::new(
-                        "custom",
-                        Wasm((0, 0, 0).into()),
-                        requestor::Package::Archive(package_path)
-                    )
-                    .with_subnet(#subnet)
-                    .with_max_budget_ngnt(#budget)
-                    .with_timeout(std::time::Duration::from_secs(#timeout))
-                    .with_constraints(constraints![
-                        "golem.inf.mem.gib" > 0.5,
-                        "golem.inf.storage.gib" > 1.0,
-                    ])
-                    .with_tasks(vec![commands! {
-                        #(#remote_input_paths)*
-                        run(module_name, #(#remote_input_names),*, "/workdir/out");
-                        download("/workdir/out", &output_path);
-                    }].into_iter())
-                    .on_completed(|activity_id, output| {
-                        println!("{} => {:#?}", activity_id, output);
+            None => {
+                let decoded_output =
+                    codec.decode(self.kind, quote!(output_data), "deserializing output data");
+                quote! {
+                    let output_data = task::spawn_blocking(move || {
+                        gfaas::__private::catch_panic(move || -> std::result::Result<_, gfaas::Error> {
+                            let res = #call;
+                            Ok(#encoded_output)
+                        })
+                        .and_then(|res| res)
                     })
-                    .run()
-                    .await?;
-                    // .context("running task on Yagna")?;
-
-                    let output_data = fs::read(&output_path).context("reading output data from file")?;
-                    let res = serde_json::from_slice(&output_data).context("deserializing output data")?;
-                    Ok(res)
+                    .await
+                    .map_err(|err| gfaas::Error::Other(err.into()))??;
+                    let res: #host_type = #decoded_output;
                 }
             }
         }
-    };
+    }
 
-    let mut inputs = vec![];
-    let mut input_args = VecDeque::with_capacity(args.len());
-    for i in 0..args.len() {
-        let in_ident = format_ident!("in{}", i);
-        let ts = quote! {
-            let next_arg = args.pop().unwrap();
-            let #in_ident = fs::read(next_arg).unwrap();
-            let #in_ident = serde_json::from_slice(&#in_ident).unwrap();
+    /// Statements of the Wasm module writing `res` to `out`.
+    fn guest_write(&self, codec: &Codec) -> TokenStream {
+        let guest_encode = &codec.guest_encode;
+        let encoded = if self.kind == Kind::Raw {
+            quote!(res)
+        } else {
+            quote!(#guest_encode(&res).unwrap())
         };
-        inputs.push(ts);
-        input_args.push_front(quote!(#in_ident));
+        match self.kind {
+            // Every item is written as soon as it is produced, prefixed with its length.
+            _ if self.stream_item.is_some() => quote! {
+                let mut out = io::BufWriter::new(fs::File::create(out).unwrap());
+                out.write_all(&[0u8]).unwrap();
+                for res in res {
+                    let serialized = #encoded;
+                    out.write_all(&(serialized.len() as u32).to_le_bytes()).unwrap();
+                    out.write_all(&serialized).unwrap();
+                }
+                out.flush().unwrap();
+            },
+            Kind::File => quote! {
+                let mut out = fs::File::create(out).unwrap();
+                out.write_all(&[0u8]).unwrap();
+                io::copy(&mut fs::File::open(res.path()).unwrap(), &mut out).unwrap();
+            },
+            _ => quote! {
+                let mut serialized = vec![0u8];
+                serialized.extend(#encoded);
+
+                fs::write(out, &serialized).unwrap();
+            },
+        }
+    }
+}
+
+/// Returns `true` if the function passes any files or directories as is.
+fn uses_files(args: &[Arg], output: &Output) -> bool {
+    output.kind == Kind::File
+        || args
+            .iter()
+            .any(|(_, _, kind)| matches!(kind, Kind::File | Kind::Dir))
+}
+
+/// The Wasm module doesn't depend on gfaas, so it gets its own minimal copy of the file types
+/// which the functions can refer to either as `gfaas::RemoteFile` or `RemoteFile`.
+fn guest_file_types() -> TokenStream {
+    quote! {
+        #[allow(dead_code)]
+        mod gfaas {
+            use std::path::{Path, PathBuf};
+
+            #[derive(Debug, Clone, PartialEq, Eq)]
+            pub struct RemoteFile {
+                path: PathBuf,
+            }
+
+            impl RemoteFile {
+                pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+                    Self { path: path.into() }
+                }
+
+                pub fn path(&self) -> &Path {
+                    &self.path
+                }
+
+                pub fn into_path_buf(self) -> PathBuf {
+                    self.path
+                }
+            }
+
+            impl AsRef<Path> for RemoteFile {
+                fn as_ref(&self) -> &Path {
+                    &self.path
+                }
+            }
+
+            #[derive(Debug, Clone, PartialEq, Eq)]
+            pub struct RemoteDir {
+                path: PathBuf,
+            }
+
+            impl RemoteDir {
+                pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+                    Self { path: path.into() }
+                }
+
+                pub fn path(&self) -> &Path {
+                    &self.path
+                }
+
+                pub fn into_path_buf(self) -> PathBuf {
+                    self.path
+                }
+            }
+
+            impl AsRef<Path> for RemoteDir {
+                fn as_ref(&self) -> &Path {
+                    &self.path
+                }
+            }
+        }
+
+        #[allow(unused_imports)]
+        use gfaas::{RemoteDir, RemoteFile};
     }
-    let args_in_order = input_args.as_slices().0;
-    let contents = quote! {
-        #preserved
+}
 
+/// `main` of the Wasm module, running `body` with the `args` and the path to the output `out`.
+/// `locate` maps the `file`, `line` and `column` of a panic back to the user's sources, see
+/// `locate_fn`.
+fn guest_main(body: TokenStream, locate: TokenStream) -> TokenStream {
+    quote! {
         fn main() {
             use std::fs;
             use std::env;
+            #[allow(unused_imports)]
+            use std::io::{self, Write};
+            use std::panic;
 
             let mut args: Vec<_> = env::args().collect();
             let out = args.pop().unwrap();
-            #(#inputs)*
 
-            let res = #fn_ident(#(#args_in_order),*);
-            let serialized = serde_json::to_vec(&res).unwrap();
+            // On panic, write the failure record tagged with 1 in place of the output, and
+            // exit normally so that the backend still gets to download it.
+            let panic_out = out.clone();
+            panic::set_hook(Box::new(move |info| {
+                let payload = info.payload();
+                let message = if let Some(message) = payload.downcast_ref::<&str>() {
+                    message.to_string()
+                } else if let Some(message) = payload.downcast_ref::<String>() {
+                    message.clone()
+                } else {
+                    "Box<Any>".to_owned()
+                };
+                let location = info.location().map(|location| {
+                    let (file, line, column) = (location.file(), location.line(), location.column());
+                    #locate.unwrap_or_else(|| location.to_string())
+                });
+                let backtrace = std::backtrace::Backtrace::capture();
+                let backtrace = match backtrace.status() {
+                    std::backtrace::BacktraceStatus::Captured => Some(backtrace.to_string()),
+                    _ => None,
+                };
+                eprintln!(
+                    "panicked at '{}', {}",
+                    message,
+                    location.as_deref().unwrap_or("<unknown>")
+                );
+                if let Some(backtrace) = &backtrace {
+                    eprintln!("{}", backtrace);
+                }
+                let record = serde_json::json!({
+                    "message": message,
+                    "location": location,
+                    "backtrace": backtrace,
+                });
+                let mut data = vec![1u8];
+                data.extend(serde_json::to_vec(&record).unwrap_or_default());
+                let _ = fs::write(&panic_out, data);
+                std::process::exit(0);
+            }));
+
+            #body
+        }
+    }
+}
+
+/// Prefix of the placeholders of the groups copied verbatim by `render`.
+const VERBATIM: &str = "__gfaas_verbatim_";
+
+/// Renders `tokens` as the source of a Wasm module. The groups in braces found verbatim in the
+/// user's sources, e.g., the bodies of the functions, are copied from the sources along with
+/// their layout, so that the lines within them map to those of the sources. Returns the source
+/// along with the regions of the lines copied, as `(first, last, origin)` where `origin` is the
+/// line of the user's sources which the line `first` is copied from.
+fn render(tokens: TokenStream) -> (String, Vec<(usize, usize, usize)>) {
+    let mut copies = vec![];
+    let text = extract_verbatim(tokens, &mut copies).to_string();
+    let mut source = String::with_capacity(text.len());
+    let mut regions = vec![];
+    let mut lines = 1;
+    let mut rest = text.as_str();
+    for (i, (copy, origin, column)) in copies.into_iter().enumerate() {
+        // The placeholders come in order, so the next one is the first found.
+        let placeholder = format!("{}{}", VERBATIM, i);
+        let pos = rest.find(&placeholder).unwrap_or(rest.len());
+        let before = &rest[..pos];
+        lines += before.matches('\n').count() + 1;
+        let first = lines;
+        lines += copy.matches('\n').count() + 1;
+        regions.push((first, lines - 1, origin));
+        source.push_str(before);
+        source.push('\n');
+        source.push_str(&" ".repeat(column));
+        source.push_str(&copy);
+        source.push('\n');
+        rest = rest.get(pos + placeholder.len()..).unwrap_or_default();
+    }
+    source.push_str(rest);
+    (source, regions)
+}
+
+/// Replaces the groups of `tokens` in braces found verbatim in the user's sources with
+/// placeholders, collecting the text of every group along with its line and column in `copies`.
+fn extract_verbatim(tokens: TokenStream, copies: &mut Vec<(String, usize, usize)>) -> TokenStream {
+    tokens
+        .into_iter()
+        .map(|token| match token {
+            TokenTree::Group(group) => {
+                if group.delimiter() == Delimiter::Brace {
+                    if let Some(copy) = verbatim(&group) {
+                        let placeholder = format_ident!("{}{}", VERBATIM, copies.len());
+                        copies.push(copy);
+                        return TokenTree::Ident(placeholder);
+                    }
+                }
+                let mut extracted =
+                    Group::new(group.delimiter(), extract_verbatim(group.stream(), copies));
+                extracted.set_span(group.span());
+                TokenTree::Group(extracted)
+            }
+            token => token,
+        })
+        .collect()
+}
+
+/// Returns the text of the `group` in the user's sources, along with its line and column, if
+/// the group is found there verbatim, i.e., it's neither generated nor modified by the macros.
+/// The line is known since Rust 1.88 only, so the group isn't copied with older compilers.
+fn verbatim(group: &Group) -> Option<(String, usize, usize)> {
+    let start = group.span().start();
+    if start.line == 0 {
+        return None;
+    }
+    let text = group.span().source_text()?;
+    let parsed: TokenStream = text.parse().ok()?;
+    let tokens = TokenStream::from(TokenTree::Group(group.clone()));
+    if !same_tokens(parsed, tokens) {
+        return None;
+    }
+    Some((text, start.line, start.column))
+}
+
+/// Whether the token streams `a` and `b` consist of the same tokens, regardless of their spacing.
+fn same_tokens(a: TokenStream, b: TokenStream) -> bool {
+    let (mut a, mut b) = (a.into_iter(), b.into_iter());
+    loop {
+        let same = match (a.next(), b.next()) {
+            (None, None) => return true,
+            (Some(TokenTree::Group(a)), Some(TokenTree::Group(b))) => {
+                a.delimiter() == b.delimiter() && same_tokens(a.stream(), b.stream())
+            }
+            (Some(TokenTree::Ident(a)), Some(TokenTree::Ident(b))) => a == b,
+            (Some(TokenTree::Punct(a)), Some(TokenTree::Punct(b))) => a.as_char() == b.as_char(),
+            (Some(TokenTree::Literal(a)), Some(TokenTree::Literal(b))) => {
+                a.to_string() == b.to_string()
+            }
+            _ => false,
+        };
+        if !same {
+            return false;
+        }
+    }
+}
+
+/// `__gfaas_locate` of the Wasm module source file with the `regions` of lines copied from the
+/// file of the item found in `source`, which maps the `file`, `line` and `column` of a panic
+/// within the source file back to the user's sources, or else to the location of the item if
+/// the panic is found in the generated code. The panics in other files are looked up in the
+/// `nested` modules including files of their own.
+fn locate_fn(regions: &[(usize, usize, usize)], source: &Source, nested: &[Ident]) -> TokenStream {
+    let origin = source.file().unwrap_or_default();
+    let regions =
+        regions
+            .iter()
+            .filter(|_| source.file().is_some())
+            .map(|&(first, last, origin)| {
+                let (first, last, origin) = (first as u32, last as u32, origin as u32);
+                quote!((#first, #last, #origin))
+            });
+    let item = match &source.location {
+        Some(location) => quote!(Some(#location.to_owned())),
+        None => quote!(None),
+    };
+    quote! {
+        #[allow(dead_code)]
+        pub(crate) fn __gfaas_locate(file: &str, line: u32, column: u32) -> Option<String> {
+            if file != file!() {
+                return None #(.or_else(|| #nested::__gfaas_locate(file, line, column)))*;
+            }
+            let regions: &[(u32, u32, u32)] = &[#(#regions),*];
+            for &(first, last, origin) in regions {
+                if first <= line && line <= last {
+                    return Some(format!("{}:{}:{}", #origin, origin + line - first, column));
+                }
+            }
+            #item
+        }
+    }
+}
+
+/// Writes the `contents` of a Wasm module source file to `path` within the `src` dir of the
+/// crate of the Wasm modules in `out_dir`.
+fn write_guest(out_dir: &str, path: &[&str], contents: impl Display) -> syn::Result<()> {
+    let mut out_path = Path::new(out_dir).join("src");
+    out_path.extend(path);
+    out_path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| File::create(&out_path))
+        .and_then(|mut out| writeln!(out, "{}", contents))
+        .map_err(|err| {
+            syn::Error::new(
+                Span::call_site(),
+                format!("generating Wasm src file {}: {}", out_path.display(), err),
+            )
+        })
+}
+
+/// Prefix of the header of the Wasm module source files, followed by the location of the item
+/// which the file is generated for, and the digest of the file of the item.
+const HEADER: &str = "// Generated by gfaas from ";
 
-            fs::write(out, &serialized).unwrap();
+/// Writes the Wasm module source file like `write_guest`, headed by the location of the item
+/// `ident` found in `source`, failing if the file has been generated for another item which is
+/// still found in the sources, i.e., an item at another location of a file whose digest is the
+/// one recorded in the header. The files generated for the previous versions of the sources,
+/// e.g., for the item before it moved within its file, are simply overwritten.
+///
+/// Unless `locate` is `None`, the file is rendered with `render` and followed by `locate_fn`,
+/// which looks up the panics in other files in the modules `locate`.
+fn write_unique_guest(
+    out_dir: &str,
+    path: &[&str],
+    contents: TokenStream,
+    locate: Option<&[Ident]>,
+    ident: &Ident,
+    source: &Source,
+) -> syn::Result<()> {
+    let mut out_path = Path::new(out_dir).join("src");
+    out_path.extend(path);
+    let header = match &source.location {
+        Some(location) => {
+            let other = fs::read_to_string(&out_path).ok().and_then(|other| {
+                let other = other.lines().next()?.strip_prefix(HEADER)?;
+                let (other, digest) = other.strip_suffix(')')?.rsplit_once(" (")?;
+                let digest = u64::from_str_radix(digest, 16).ok()?;
+                let file = other.rsplitn(3, ':').nth(2)?;
+                Some(other.to_owned())
+                    .filter(|other| other != location)
+                    .filter(|_| source::digest(Path::new(file)) == Some(digest))
+            });
+            if let Some(other) = other {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    format!(
+                        "Wasm module of '{}' collides with that of another item of the same \
+                         name at {}: rename either of them",
+                        ident, other
+                    ),
+                ));
+            }
+            let digest = source
+                .digest
+                .map_or_else(|| "unknown".to_owned(), |digest| format!("{:016x}", digest));
+            format!("{}{} ({})\n", HEADER, location, digest)
         }
+        None => String::new(),
     };
+    let nested = match locate {
+        Some(nested) => nested,
+        None => return write_guest(out_dir, path, format_args!("{}{}", header, contents)),
+    };
+    let (text, regions) = render(contents);
+    let offset = header.lines().count();
+    let regions: Vec<_> = regions
+        .into_iter()
+        .map(|(first, last, origin)| (first + offset, last + offset, origin))
+        .collect();
+    let locate = locate_fn(&regions, source, nested);
+    write_guest(
+        out_dir,
+        path,
+        format_args!("{}{}\n\n{}", header, text, locate),
+    )
+}
 
-    // push body of the function into a Wasm module
-    let out_dir = env::var("GFAAS_OUT_DIR")
-        .expect("GFAAS_OUT_DIR should be defined. Did you build the project with gfaas tool?");
-    let out_path = Path::new(&out_dir)
-        .join("gfaas_modules")
-        .join("src")
-        .join("bin")
-        .join(format!("{}.rs", fn_ident.to_string()));
-    let mut out = File::create(out_path).unwrap_or_else(|_| {
-        panic!(
-            "generating Wasm src file {}",
-            [&out_dir, "gfaas.rs"].join("/")
-        )
-    });
-    writeln!(out, "{}", contents).unwrap();
+/// Returns the name of the Wasm module of the item `path`, i.e., of the item within the type,
+/// the group, or the `remote_mod` hosting it, if any, prefixed with the crate of `package`, and
+/// suffixed with the hash of the path and the `origin` of the item, e.g., `app-area-1f2e3d4c`
+/// for `area`, or `app-shapes-area-5a6b7c8d` for `area` of `remote_mod` `shapes`. The origin is
+/// the location of the item, so that the items of the same name in different modules don't
+/// collide, or else whatever the items sharing the Wasm module have in common, e.g., the file
+/// of a `remote_impl` type and its impl block.
+fn mangle(package: &PackageConfig, path: &[&str], origin: &str) -> String {
+    let path = path.join("-");
+    let hash = source::stable_hash(format!("{} {}", path, origin).as_bytes()) >> 32;
+    match package.crate_name.as_str() {
+        "" => format!("{}-{:08x}", path, hash),
+        crate_name => format!("{}-{}-{:08x}", crate_name, path, hash),
+    }
+}
 
-    output
+/// Writes the `main` of the Wasm module of the `group`, dispatching to every function found in
+/// the group so far. Since the functions are expanded one by one, it is rewritten every time
+/// a function of the group is, and so it ends up listing all of them. gfaas build tool clears
+/// the sources of the Wasm modules before every build, so none of the functions are stale.
+fn write_group_main(out_dir: &str, group: &str) -> syn::Result<()> {
+    let group_path = Path::new(out_dir).join("src").join("bin").join(group);
+    let mut functions = fs::read_dir(&group_path)
+        .and_then(|entries| {
+            entries
+                .map(|entry| Ok(entry?.path()))
+                .collect::<std::io::Result<Vec<_>>>()
+        })
+        .map_err(|err| {
+            syn::Error::new(
+                Span::call_site(),
+                format!("listing functions in {}: {}", group_path.display(), err),
+            )
+        })?
+        .into_iter()
+        .filter(|path| path.extension() == Some("rs".as_ref()))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_owned()))
+        .filter(|name| name != "main")
+        .collect::<Vec<_>>();
+    functions.sort();
+    let idents: Vec<_> = functions
+        .iter()
+        .map(|name| format_ident!("{}", name))
+        .collect();
+    let main = guest_main(
+        quote! {
+            let function = args.remove(1);
+            match function.as_str() {
+                #(#functions => #idents::__gfaas_run(args, out),)*
+                function => panic!("unknown function '{}'", function),
+            }
+        },
+        quote!(None #(.or_else(|| #idents::__gfaas_locate(file, line, column)))*),
+    );
+    write_guest(
+        out_dir,
+        &["bin", group, "main.rs"],
+        quote! {
+            #(
+                #[allow(non_snake_case)]
+                mod #idents;
+            )*
+
+            #main
+        },
+    )
+}
+
+/// Lets gfaas build tool know which codec crates the Wasm modules depend on.
+fn register_codec(out_dir: &str, codec: &Codec) -> syn::Result<()> {
+    let codecs_path = Path::new(out_dir).join("codecs");
+    fs::create_dir_all(&codecs_path)
+        .and_then(|_| File::create(codecs_path.join(&codec.name)))
+        .map(|_| ())
+        .map_err(|err| {
+            syn::Error::new(
+                Span::call_site(),
+                format!("registering codec in {}: {}", codecs_path.display(), err),
+            )
+        })
+}
+
+/// Expands the remote function `f`, whose arguments default to the settings of its `package`.
+/// The Wasm module sources are generated into the crate `out_dir` if given, otherwise there is
+/// no Wasm module to run, and so the function fails unless it is run natively, or by a backend
+/// which doesn't need one.
+pub(super) fn remote_fn_impl(
+    attrs: GwasmAttrs,
+    mut f: GwasmFn,
+    package: &PackageConfig,
+    source: Source,
+    out_dir: Option<String>,
+) -> syn::Result<TokenStream> {
+    let params = GwasmParams::parse(attrs, package)?;
+    // Functions of a `remote_mod` are expanded after the module, which tells them the name of
+    // its copy, made of the name of the module.
+    let remote_mod = params.remote_mod.as_ref().map(|name| {
+        let module = name.rsplit('-').nth(1).unwrap_or(name);
+        (name, format_ident!("{}", module))
+    });
+
+    // Validate and extract arguments
+    let args = validate_extract_args(&mut f.args)?;
+    // Expand into gWasm connector code
+    let raw_output = extract_marker(&mut f.attrs, "raw");
+    let fn_attrs = f.attrs;
+    let fn_vis = f.vis;
+    let fn_constness = f.constness;
+    let fn_unsafety = f.unsafety;
+    let fn_ident = f.ident;
+    let fn_generics = f.generics;
+    let fn_args = f.args;
+    let fn_ret = f.ret;
+    let fn_body = f.body;
+    let output = Output::new(&fn_ret, raw_output)?;
+    let output_type = output.ty();
+    let host_type = output.host_type();
+
+    // Generic functions are compiled into a Wasm module per instantiation. The host picks
+    // the module with the `Instance` trait implemented for the type parameters (as a tuple)
+    // of every instantiation, which also rules out calling the function with other types.
+    let type_params: Vec<_> = fn_generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect();
+    if let Some(param) = fn_generics.const_params().next() {
+        return Err(syn::Error::new_spanned(
+            param,
+            "functions with const generic parameters are unsupported",
+        ));
+    }
+    if let (true, Some(ty)) = (type_params.is_empty(), params.instantiate.first()) {
+        return Err(syn::Error::new_spanned(
+            ty,
+            "'instantiate(...)' is only applicable to generic functions",
+        ));
+    }
+    if !type_params.is_empty() && params.instantiate.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &fn_generics,
+            "generic functions require 'instantiate(...)' listing the types to instantiate them \
+             with, since every instantiation is compiled into a separate Wasm module",
+        ));
+    }
+    let mut instances = vec![];
+    let mut instance_names = BTreeSet::new();
+    for ty in &params.instantiate {
+        let types: Vec<Type> = match ty {
+            Type::Tuple(tuple) if type_params.len() != 1 => tuple.elems.iter().cloned().collect(),
+            ty => vec![ty.clone()],
+        };
+        if types.len() != type_params.len() {
+            return Err(syn::Error::new_spanned(
+                ty,
+                format!(
+                    "instantiation doesn't match the {} type parameters of the function",
+                    type_params.len()
+                ),
+            ));
+        }
+        let name = instance_name(&fn_ident, &types);
+        if !instance_names.insert(name.clone()) {
+            return Err(syn::Error::new_spanned(ty, "duplicate instantiation"));
+        }
+        instances.push((types, name));
+    }
+    // Functions of a group share its Wasm module, which runs the one named by the first
+    // argument.
+    let group = params
+        .group
+        .as_ref()
+        .map(|group| mangle(package, &[&group.value()], "group"));
+    let location = source.location.as_deref().unwrap_or_default();
+    let module_name = |name: &str| match (&group, &remote_mod) {
+        (Some(group), _) => format!("{}::{}", group, name),
+        (None, Some((_, module))) => mangle(package, &[&module.to_string(), name], location),
+        (None, None) => mangle(package, &[name], location),
+    };
+    let (module, turbofish) = if type_params.is_empty() {
+        let name = module_name(&fn_ident.to_string());
+        (quote!(#name), quote!())
+    } else {
+        (
+            quote!(<(#(#type_params,)*) as #fn_ident::Instance>::MODULE),
+            quote!(::<#(#type_params),*>),
+        )
+    };
+    let instance_impls = if type_params.is_empty() {
+        quote!()
+    } else {
+        let impls = instances.iter().map(|(types, name)| {
+            let name = module_name(name);
+            quote! {
+                impl Instance for (#(#types,)*) {
+                    const MODULE: &'static str = #name;
+                }
+            }
+        });
+        quote! {
+            #[doc(hidden)]
+            pub trait Instance {
+                const MODULE: &'static str;
+            }
+
+            #(#impls)*
+        }
+    };
+
+    // The host side needs to (de)serialize the values involving the type parameters, and has
+    // to restrict them to the instantiations.
+    let mut host_generics = fn_generics.clone();
+    if !type_params.is_empty() {
+        let output_value = match output.kind {
+            Kind::Value => Some(output_type),
+            _ => None,
+        };
+        let value_types = args
+            .iter()
+            .filter(|(_, _, kind)| *kind == Kind::Value)
+            .map(|(_, ty, _)| &**ty)
+            .chain(output_value)
+            .filter(|ty| mentions_any(ty, &type_params));
+        let predicates = &mut host_generics.make_where_clause().predicates;
+        predicates.push(parse_quote!((#(#type_params,)*): #fn_ident::Instance));
+        for param in &type_params {
+            predicates.push(parse_quote!(#param: 'static));
+        }
+        for ty in value_types {
+            predicates.push(parse_quote! {
+                #ty: gfaas::__private::serde::Serialize
+                    + gfaas::__private::serde::de::DeserializeOwned
+            });
+        }
+        if let Some(item) = &output.stream_item {
+            if mentions_any(item, &type_params) {
+                predicates.push(parse_quote!(#item: Send));
+            }
+        }
+    }
+    let (host_impl_generics, _, host_where_clause) = host_generics.split_for_impl();
+    let (fn_impl_generics, _, fn_where_clause) = fn_generics.split_for_impl();
+
+    // Unsafe functions stay unsafe to call on the host.
+    let call = |args: TokenStream| {
+        if fn_unsafety.is_some() {
+            quote!(unsafe { #fn_ident #turbofish(#args) })
+        } else {
+            quote!(#fn_ident #turbofish(#args))
+        }
+    };
+
+    // The function as written by the user, only without the `#[raw]` markers.
+    let fn_item = quote! {
+        #fn_constness #fn_unsafety fn #fn_ident #fn_impl_generics(#fn_args) #fn_ret #fn_where_clause #fn_body
+    };
+    let preserved = quote! {
+        #(#fn_attrs)*
+        #fn_vis #fn_item
+    };
+
+    let (host_ret, wrap_res, unwrap_res) = output.flatten();
+    let backend = params.backend();
+    let codec = Codec::new(params.codec.as_deref());
+    let config = params.config();
+
+    let serialized_args: Vec<_> = args
+        .iter()
+        .map(|(arg, _, kind)| codec.encode(*kind, quote!(#arg), "serializing input data"))
+        .collect();
+
+    // Companion module hosting the `map` function which runs the remote function on
+    // a batch of inputs.
+    let in_idents: Vec<_> = (0..args.len()).map(|i| format_ident!("in{}", i)).collect();
+    let (item_ty, item_pat) = match args.as_slice() {
+        [(_, ty, _)] => (quote!(#ty), quote!(#(#in_idents)*)),
+        _ => {
+            let tys = args.iter().map(|(_, ty, _)| ty);
+            (quote!((#(#tys),*)), quote!((#(#in_idents),*)))
+        }
+    };
+    let inputs_param = if type_params.iter().any(|param| param == "I") {
+        format_ident!("__I")
+    } else {
+        format_ident!("I")
+    };
+    let mut map_generics = host_generics.clone();
+    map_generics.params.push(parse_quote!(#inputs_param));
+    map_generics
+        .make_where_clause()
+        .predicates
+        .push(parse_quote!(#inputs_param: IntoIterator<Item = #item_ty>));
+    let (map_impl_generics, _, map_where_clause) = map_generics.split_for_impl();
+    let map_vis = match fn_vis {
+        Visibility::Inherited => quote!(pub(super)),
+        ref vis => quote!(#vis),
+    };
+    let map_doc = format!(
+        "Runs [`{0}`](fn.{0}.html) once for every element of `inputs`. `concurrency` is an upper \
+         bound on the number of invocations running at the same time; depending on the backend, \
+         they may as well run one after another. The outputs are returned in the same order as \
+         `inputs`.",
+        fn_ident
+    );
+
+    // The `call_in` companion runs the remote function in a session.
+    let session = if args
+        .iter()
+        .any(|(arg, _, _)| arg_name(arg).to_string() == "session")
+    {
+        format_ident!("__session")
+    } else {
+        format_ident!("session")
+    };
+    let call_in_doc = format!(
+        "Runs [`{0}`](fn.{0}.html) in the `{1}`, reusing the backend prepared for its Wasm \
+         module by the previous calls.",
+        fn_ident, session
+    );
+
+    let input_args: Vec<_> = args
+        .iter()
+        .map(|(arg, _, kind)| codec.input(*kind, quote!(#arg)))
+        .collect();
+    let batch_args: Vec<_> = args
+        .iter()
+        .zip(&in_idents)
+        .map(|((_, _, kind), in_ident)| codec.input(*kind, quote!(#in_ident)))
+        .collect();
+    let decoded_output = output.decoded(&codec);
+
+    if params.native() {
+        let decoded_args: Vec<_> = args
+            .iter()
+            .zip(&in_idents)
+            .map(|((_, _, kind), in_ident)| {
+                codec.decode(*kind, quote!(#in_ident), "deserializing input data")
+            })
+            .collect();
+        let run = output.native_run(&codec, call(quote!(#(#decoded_args),*)));
+        let arg_names = args.iter().map(|(arg, _, _)| arg_name(arg));
+        let native_call = if fn_unsafety.is_some() {
+            quote!(unsafe { super::#fn_ident #turbofish(#(#arg_names),*) })
+        } else {
+            quote!(super::#fn_ident #turbofish(#(#arg_names),*))
+        };
+
+        return Ok(quote! {
+            #(#fn_attrs)*
+            #fn_vis async #fn_unsafety fn #fn_ident #host_impl_generics(#fn_args) -> #host_ret #host_where_clause {
+                use gfaas::__private::anyhow::Context;
+                use gfaas::__private::tokio::task;
+
+                #fn_item
+
+                let (#(#in_idents,)*) = (#(#serialized_args,)*);
+                #run
+                #wrap_res
+            }
+
+            #fn_vis mod #fn_ident {
+                #[allow(unused_imports)]
+                use super::*;
+
+                #instance_impls
+
+                #[doc = #map_doc]
+                #map_vis async #fn_unsafety fn map #map_impl_generics(inputs: #inputs_param, concurrency: usize) -> std::result::Result<Vec<#host_type>, gfaas::Error>
+                #map_where_clause
+                {
+                    use gfaas::__private::futures::{
+                        future::FutureExt,
+                        stream::{self, StreamExt, TryStreamExt},
+                    };
+
+                    stream::iter(inputs)
+                        .map(|#item_pat| super::#fn_ident(#(#in_idents),*).map(|res| #unwrap_res))
+                        .buffered(concurrency.max(1))
+                        .try_collect()
+                        .await
+                }
+
+                #[doc = #call_in_doc]
+                #map_vis async #fn_unsafety fn call_in #host_impl_generics(#session: &gfaas::Session, #fn_args) -> #host_ret #host_where_clause {
+                    let _ = #session;
+                    (#native_call).await
+                }
+            }
+        });
+    }
+
+    let expanded = quote! {
+        #(#fn_attrs)*
+        #fn_vis async #fn_unsafety fn #fn_ident #host_impl_generics(#fn_args) -> #host_ret #host_where_clause {
+            use gfaas::__private::anyhow::Context;
+
+            #[allow(unused_mut)]
+            let mut config = gfaas::Config::default();
+            #(#config)*
+
+            let inputs = vec![#(#input_args),*];
+            let output_data = gfaas::dispatch::<#backend>(#module, &config, inputs).await?;
+            let res: #host_type = #decoded_output;
+            #wrap_res
+        }
+
+        #fn_vis mod #fn_ident {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #instance_impls
+
+            #[doc = #map_doc]
+            #map_vis async #fn_unsafety fn map #map_impl_generics(inputs: #inputs_param, concurrency: usize) -> std::result::Result<Vec<#host_type>, gfaas::Error>
+            #map_where_clause
+            {
+                use gfaas::__private::anyhow::Context;
+
+                #[allow(unused_mut)]
+                let mut config = gfaas::Config::default();
+                #(#config)*
+
+                let tasks = inputs
+                    .into_iter()
+                    .map(|#item_pat| -> std::result::Result<Vec<gfaas::backend::Input>, gfaas::Error> {
+                        Ok(vec![#(#batch_args),*])
+                    })
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                let outputs = gfaas::dispatch_batch::<#backend>(#module, &config, tasks, concurrency).await?;
+                outputs
+                    .into_iter()
+                    .map(|output_data| Ok(#decoded_output))
+                    .collect()
+            }
+
+            #[doc = #call_in_doc]
+            #map_vis async #fn_unsafety fn call_in #host_impl_generics(#session: &gfaas::Session, #fn_args) -> #host_ret #host_where_clause {
+                use gfaas::__private::anyhow::Context;
+
+                let inputs = vec![#(#input_args),*];
+                let output_data = #session.dispatch::<#backend>(#module, inputs).await?;
+                let res: #host_type = #decoded_output;
+                #wrap_res
+            }
+        }
+    };
+
+    // Without an output dir, there is nowhere to put the Wasm module, so dispatching the
+    // function fails at runtime, unless the selected backend doesn't need the module, e.g., Mock.
+    let out_dir = match out_dir {
+        Some(out_dir) => out_dir,
+        None => return Ok(expanded),
+    };
+
+    let inputs = codec.guest_inputs(&args, &in_idents);
+    let write_output = output.guest_write(&codec);
+    // The functions of a `remote_mod` may refer to the file types via the rest of the module.
+    let file_types = if uses_files(&args, &output) || params.remote_mod.is_some() {
+        guest_file_types()
+    } else {
+        quote!()
+    };
+    // One Wasm module per instantiation of a generic function, each calling the function with
+    // the concrete types.
+    let modules: Vec<(String, TokenStream)> = if instances.is_empty() {
+        vec![(fn_ident.to_string(), quote!())]
+    } else {
+        instances
+            .iter()
+            .map(|(types, name)| (name.clone(), quote!(::<#(#types),*>)))
+            .collect()
+    };
+    for (name, turbofish) in &modules {
+        let guest_call = if fn_unsafety.is_some() {
+            quote!(unsafe { #fn_ident #turbofish(#(#in_idents),*) })
+        } else {
+            quote!(#fn_ident #turbofish(#(#in_idents),*))
+        };
+        let run = quote! {
+            #(#inputs)*
+
+            // If the function returns `Result<T, E>`, it is serialized as a tagged envelope,
+            // i.e., `{"Ok": ...}` or `{"Err": ...}`, which the host unpacks into `RemoteError<E>`.
+            let res = #guest_call;
+            #write_output
+        };
+        // Functions of a `remote_mod` come with the copy of the whole module, and are run from
+        // within it, so that its private items are in scope.
+        let (preserved, run, nested) = match &remote_mod {
+            Some((name, module)) => {
+                let path = format!("/src/remote_mod/{}.rs", name);
+                let preserved = quote! {
+                    #[allow(dead_code)]
+                    mod #module {
+                        #[allow(unused_imports)]
+                        use super::gfaas;
+
+                        include!(concat!(env!("CARGO_MANIFEST_DIR"), #path));
+
+                        #[allow(unused_mut)]
+                        pub(super) fn __gfaas_run(mut args: Vec<String>, out: String) {
+                            use std::fs;
+                            #[allow(unused_imports)]
+                            use std::io::{self, Write};
+
+                            #run
+                        }
+                    }
+                };
+                (
+                    preserved,
+                    quote!(#module::__gfaas_run(args, out);),
+                    vec![module.clone()],
+                )
+            }
+            None => (preserved.clone(), run, vec![]),
+        };
+        // push body of the function into a Wasm module, or into a module of the group's one
+        match &group {
+            Some(group) => {
+                write_unique_guest(
+                    &out_dir,
+                    &["bin", group, &format!("{}.rs", name)],
+                    quote! {
+                        #file_types
+
+                        #preserved
+
+                        #[allow(unused_mut)]
+                        pub fn __gfaas_run(mut args: Vec<String>, out: String) {
+                            #[allow(unused_imports)]
+                            use std::fs;
+                            #[allow(unused_imports)]
+                            use std::io::{self, Write};
+
+                            #run
+                        }
+                    },
+                    Some(&nested),
+                    &fn_ident,
+                    &source,
+                )?;
+            }
+            None => {
+                let main = guest_main(run, quote!(__gfaas_locate(file, line, column)));
+                write_unique_guest(
+                    &out_dir,
+                    &["bin", &format!("{}.rs", module_name(name))],
+                    quote! {
+                        #file_types
+
+                        #preserved
+
+                        #main
+                    },
+                    Some(&nested),
+                    &fn_ident,
+                    &source,
+                )?;
+            }
+        }
+    }
+    if let Some(group) = &group {
+        write_group_main(&out_dir, group)?;
+    }
+    register_codec(&out_dir, &codec)?;
+
+    Ok(expanded)
+}
+
+/// Expands `item` annotated with `remote_impl`, i.e., an inherent impl block whose functions
+/// are compiled into a single Wasm module named after the type, or the definition of the type
+/// itself which the Wasm module needs as well.
+pub(super) fn remote_impl_impl(
+    attrs: GwasmAttrs,
+    item: Item,
+    package: &PackageConfig,
+    source: Source,
+    out_dir: Option<String>,
+) -> syn::Result<TokenStream> {
+    let mut item_impl = match item {
+        Item::Impl(item_impl) => item_impl,
+        Item::Struct(ItemStruct { ref ident, .. }) | Item::Enum(ItemEnum { ref ident, .. }) => {
+            if let Some(GwasmAttr::Value { ident, .. } | GwasmAttr::Types { ident, .. }) =
+                attrs.0.first()
+            {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    "arguments to 'remote_impl' belong to the impl block, not to the type",
+                ));
+            }
+            if let Some(out_dir) = out_dir {
+                let file = source.file().unwrap_or_default();
+                let module = mangle(package, &[&ident.to_string()], file);
+                write_unique_guest(
+                    &out_dir,
+                    &["bin", &module, "type.rs"],
+                    quote!(#item),
+                    None,
+                    ident,
+                    &source,
+                )?;
+            }
+            return Ok(quote!(#item));
+        }
+        item => {
+            return Err(syn::Error::new_spanned(
+                item,
+                "'remote_impl' applies to impl blocks, and to the definitions of their types",
+            ))
+        }
+    };
+    let params = GwasmParams::parse(attrs, package)?;
+    if let Some(group) = &params.group {
+        return Err(syn::Error::new_spanned(
+            group,
+            "'group' is unsupported in 'remote_impl', since its functions already share \
+             a Wasm module",
+        ));
+    }
+    if let Some(ty) = params.instantiate.first() {
+        return Err(syn::Error::new_spanned(
+            ty,
+            "'instantiate(...)' is only applicable to generic functions",
+        ));
+    }
+    if let Some((_, path, _)) = &item_impl.trait_ {
+        return Err(syn::Error::new_spanned(
+            path,
+            "'remote_impl' applies to inherent impl blocks only",
+        ));
+    }
+    if !item_impl.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &item_impl.generics,
+            "generic impl blocks are unsupported",
+        ));
+    }
+    let self_ty = item_impl.self_ty.clone();
+    let type_ident = match last_segment(&self_ty) {
+        Some(segment) if segment.arguments.is_empty() => segment.ident.clone(),
+        _ => {
+            return Err(syn::Error::new_spanned(
+                self_ty,
+                "'remote_impl' requires the impl block of a type referred to by name",
+            ))
+        }
+    };
+    let file = source.file().unwrap_or_default();
+    let module = mangle(package, &[&type_ident.to_string()], file);
+
+    let backend = params.backend();
+    let codec = Codec::new(params.codec.as_deref());
+    let config = params.config();
+    let native = params.native();
+    // The original functions are kept in a hidden trait implemented for the type, so that they
+    // can be called natively despite the async functions taking their names.
+    let native_trait = format_ident!("__GfaasNative{}", type_ident);
+
+    let mut host_items = vec![];
+    let mut native_decls = vec![];
+    let mut native_items = vec![];
+    let mut guest_arms = vec![];
+    let mut uses_any_files = false;
+    for impl_item in &mut item_impl.items {
+        let method = match impl_item {
+            ImplItem::Method(method) => method,
+            impl_item => {
+                host_items.push(quote!(#impl_item));
+                continue;
+            }
+        };
+        // Functions marked with `#[local]` stay as they are, available to the other functions
+        // both on the host and in the Wasm module.
+        if extract_marker(&mut method.attrs, "local") {
+            host_items.push(quote!(#method));
+            continue;
+        }
+        let sig = &mut method.sig;
+        if let Some(asyncness) = &sig.asyncness {
+            return Err(syn::Error::new_spanned(
+                asyncness,
+                "async functions are unsupported: mark them '#[local]' to keep them on the host",
+            ));
+        }
+        if !sig.generics.params.is_empty() {
+            return Err(syn::Error::new_spanned(
+                &sig.generics,
+                "generic functions are unsupported in 'remote_impl': mark them '#[local]', or \
+                 make them generic 'remote_fn's instead",
+            ));
+        }
+        let receiver = match sig.inputs.first() {
+            Some(FnArg::Receiver(receiver)) => Some(Receiver::of(receiver)?),
+            _ => None,
+        };
+        // The receiver is serialized just like any other argument.
+        let mut args: Vec<Arg> = receiver
+            .map(|_| (parse_quote!(self), parse_quote!(Self), Kind::Value))
+            .into_iter()
+            .collect();
+        args.extend(validate_extract_args(
+            sig.inputs.iter_mut().skip(args.len()),
+        )?);
+        let raw_output = extract_marker(&mut method.attrs, "raw");
+        let output = Output::new(&sig.output, raw_output)?;
+        uses_any_files |= uses_files(&args, &output);
+
+        let sig = &method.sig;
+        let attrs = &method.attrs;
+        let vis = &method.vis;
+        let unsafety = &sig.unsafety;
+        let ident = &sig.ident;
+        let inputs = &sig.inputs;
+        let name = format!("{}::{}", module, ident);
+        let host_type = output.host_type();
+        let (host_ret, wrap_res, _) = output.flatten();
+        let in_idents: Vec<_> = (0..args.len()).map(|i| format_ident!("in{}", i)).collect();
+        // Unsafe functions stay unsafe to call on the host.
+        let call = |path: TokenStream, mut args: Vec<TokenStream>| {
+            if let (Some(Receiver::Ref), Some(this)) = (receiver, args.first_mut()) {
+                *this = quote!(&#this);
+            }
+            if unsafety.is_some() {
+                quote!(unsafe { #path::#ident(#(#args),*) })
+            } else {
+                quote!(#path::#ident(#(#args),*))
+            }
+        };
+
+        let body = if native {
+            let serialized_args = args
+                .iter()
+                .map(|(arg, _, kind)| codec.encode(*kind, quote!(#arg), "serializing input data"));
+            let decoded_args = args
+                .iter()
+                .zip(&in_idents)
+                .map(|((_, _, kind), in_ident)| {
+                    codec.decode(*kind, quote!(#in_ident), "deserializing input data")
+                })
+                .collect();
+            let run =
+                output.native_run(&codec, call(quote!(<Self as #native_trait>), decoded_args));
+            quote! {
+                use gfaas::__private::anyhow::Context;
+                use gfaas::__private::tokio::task;
+
+                let (#(#in_idents,)*) = (#(#serialized_args,)*);
+                #run
+                #wrap_res
+            }
+        } else {
+            let input_args = args
+                .iter()
+                .map(|(arg, _, kind)| codec.input(*kind, quote!(#arg)));
+            let decoded_output = output.decoded(&codec);
+            quote! {
+                use gfaas::__private::anyhow::Context;
+
+                #[allow(unused_mut)]
+                let mut config = gfaas::Config::default();
+                #(#config)*
+
+                let inputs = vec![#(#input_args),*];
+                let output_data = gfaas::dispatch::<#backend>(#name, &config, inputs).await?;
+                let res: #host_type = #decoded_output;
+                #wrap_res
+            }
+        };
+        host_items.push(quote! {
+            #(#attrs)*
+            #vis async #unsafety fn #ident(#inputs) -> #host_ret {
+                #body
+            }
+        });
+
+        if native {
+            let tys = args
+                .iter()
+                .skip(receiver.is_some() as usize)
+                .map(|(_, ty, _)| ty);
+            let receiver = match receiver {
+                Some(Receiver::Ref) => quote!(&self,),
+                Some(Receiver::Value) => quote!(self,),
+                None => quote!(),
+            };
+            let ret = &sig.output;
+            native_decls.push(quote! {
+                #unsafety fn #ident(#receiver #(_: #tys),*) #ret;
+            });
+            let mut native_method = method.clone();
+            native_method.vis = Visibility::Inherited;
+            native_method.sig.constness = None;
+            native_items.push(quote!(#native_method));
+        } else {
+            let inputs = codec.guest_inputs(&args, &in_idents);
+            let write_output = output.guest_write(&codec);
+            let guest_call = call(
+                quote!(<#self_ty>),
+                in_idents.iter().map(|in_ident| quote!(#in_ident)).collect(),
+            );
+            let function = ident.to_string();
+            guest_arms.push(quote! {
+                #function => {
+                    #(#inputs)*
+                    let res = #guest_call;
+                    #write_output
+                }
+            });
+        }
+    }
+
+    let impl_attrs = &item_impl.attrs;
+    let expanded = quote! {
+        #(#impl_attrs)*
+        impl #self_ty {
+            #(#host_items)*
+        }
+    };
+
+    if native {
+        return Ok(quote! {
+            #expanded
+
+            #[doc(hidden)]
+            trait #native_trait {
+                #(#native_decls)*
+            }
+
+            impl #native_trait for #self_ty {
+                #(#native_items)*
+            }
+        });
+    }
+    let out_dir = match out_dir {
+        Some(out_dir) => out_dir,
+        None => return Ok(expanded),
+    };
+
+    // The functions are all compiled into a single Wasm module, which runs the one named by
+    // the first argument.
+    let file_types = if uses_any_files {
+        guest_file_types()
+    } else {
+        quote!()
+    };
+    let main = guest_main(
+        quote! {
+            let function = args.remove(1);
+            match function.as_str() {
+                #(#guest_arms)*
+                function => panic!("unknown function '{}'", function),
+            }
+        },
+        quote!(__gfaas_locate(file, line, column)),
+    );
+    write_unique_guest(
+        &out_dir,
+        &["bin", &module, "main.rs"],
+        quote! {
+            #file_types
+
+            include!("type.rs");
+
+            #item_impl
+
+            #main
+        },
+        Some(&[]),
+        &type_ident,
+        &source,
+    )?;
+    register_codec(&out_dir, &codec)?;
+
+    Ok(expanded)
+}
+
+/// Returns the attribute of `attrs` invoking any of the macros `names`, whether via a path or
+/// an import, e.g., both `#[gfaas::remote_fn]` and `#[remote_fn]`.
+fn find_attr<'a>(attrs: &'a [Attribute], names: &[&str]) -> Option<&'a Attribute> {
+    attrs.iter().find(|attr| match attr.path.segments.last() {
+        Some(segment) => names.iter().any(|name| segment.ident == name),
+        None => false,
+    })
+}
+
+fn item_attrs(item: &Item) -> &[Attribute] {
+    match item {
+        Item::Const(item) => &item.attrs,
+        Item::Enum(item) => &item.attrs,
+        Item::Fn(item) => &item.attrs,
+        Item::Impl(item) => &item.attrs,
+        Item::Macro(item) => &item.attrs,
+        Item::Mod(item) => &item.attrs,
+        Item::Static(item) => &item.attrs,
+        Item::Struct(item) => &item.attrs,
+        Item::Trait(item) => &item.attrs,
+        Item::Type(item) => &item.attrs,
+        Item::Union(item) => &item.attrs,
+        Item::Use(item) => &item.attrs,
+        _ => &[],
+    }
+}
+
+/// Fails if any of the `items`, or the items of their inline modules, is annotated with
+/// a `gfaas` attribute, which `remote_mod` doesn't support there.
+fn reject_nested_attrs(items: &[Item]) -> syn::Result<()> {
+    for item in items {
+        if let Some(attr) = find_attr(
+            item_attrs(item),
+            &["remote_fn", "remote_impl", "remote_mod"],
+        ) {
+            return Err(syn::Error::new_spanned(
+                attr,
+                "only the functions found directly in a 'remote_mod' can be annotated with \
+                 'remote_fn'",
+            ));
+        }
+        if let Item::Mod(ItemMod {
+            content: Some((_, items)),
+            ..
+        }) = item
+        {
+            reject_nested_attrs(items)?;
+        }
+    }
+    Ok(())
+}
+
+/// Strips the imports from `gfaas` off `tree`, except for the file types which the Wasm
+/// module has its own copies of, or returns `None` if nothing is left.
+fn guest_use_tree(tree: &UseTree, in_gfaas: bool) -> Option<UseTree> {
+    match tree {
+        UseTree::Path(path) => {
+            let in_gfaas = in_gfaas || path.ident == "gfaas";
+            let subtree = guest_use_tree(&path.tree, in_gfaas)?;
+            Some(UseTree::Path(UsePath {
+                tree: Box::new(subtree),
+                ..path.clone()
+            }))
+        }
+        UseTree::Name(UseName { ident }) | UseTree::Rename(UseRename { ident, .. })
+            if in_gfaas
+                && !["self", "RemoteFile", "RemoteDir"]
+                    .iter()
+                    .any(|x| ident == x) =>
+        {
+            None
+        }
+        UseTree::Group(group) => {
+            let items: Punctuated<_, Token![,]> = group
+                .items
+                .iter()
+                .filter_map(|tree| guest_use_tree(tree, in_gfaas))
+                .collect();
+            if items.is_empty() {
+                return None;
+            }
+            Some(UseTree::Group(UseGroup {
+                items,
+                ..group.clone()
+            }))
+        }
+        tree => Some(tree.clone()),
+    }
+}
+
+/// Expands the module `item` annotated with `remote_mod`, a copy of which is included by the
+/// Wasm modules of its remote functions, so that they can refer to the other items of the
+/// module.
+pub(super) fn remote_mod_impl(
+    attrs: GwasmAttrs,
+    mut item: ItemMod,
+    package: &PackageConfig,
+    source: Source,
+    out_dir: Option<String>,
+) -> syn::Result<TokenStream> {
+    if let Some(attr) = attrs.0.first() {
+        return Err(syn::Error::new_spanned(
+            attr,
+            "'remote_mod' takes no arguments: pass them to its 'remote_fn's instead",
+        ));
+    }
+    let module = item.ident.clone();
+    let items = match &mut item.content {
+        Some((_, items)) => items,
+        None => {
+            return Err(syn::Error::new_spanned(
+                item,
+                "'remote_mod' applies to inline modules only, i.e., 'mod name { ... }'",
+            ))
+        }
+    };
+
+    // The functions are expanded after the module, and so they are told the name of its copy.
+    let location = source.location.as_deref().unwrap_or_default();
+    let name = mangle(package, &[&module.to_string()], location);
+    let mut guest_items = vec![];
+    for item in items.iter_mut() {
+        match item {
+            Item::Fn(f) if find_attr(&f.attrs, &["remote_fn"]).is_some() => {
+                // The Wasm module gets the function as written by the user, and the function
+                // gets to know which module to include.
+                let mut guest_fn = f.clone();
+                guest_fn
+                    .attrs
+                    .retain(|attr| find_attr(std::slice::from_ref(attr), &["remote_fn"]).is_none());
+                extract_marker(&mut guest_fn.attrs, "raw");
+                for arg in &mut guest_fn.sig.inputs {
+                    if let FnArg::Typed(arg) = arg {
+                        extract_marker(&mut arg.attrs, "raw");
+                    }
+                }
+                guest_items.push(Item::Fn(guest_fn));
+
+                let attr = f
+                    .attrs
+                    .iter_mut()
+                    .find(|attr| find_attr(std::slice::from_ref(*attr), &["remote_fn"]).is_some())
+                    .unwrap();
+                let mut args = if attr.tokens.is_empty() {
+                    GwasmAttrs(Punctuated::new())
+                } else {
+                    attr.parse_args()?
+                };
+                args.0.push(parse_quote!(__remote_mod = #name));
+                let args = &args.0;
+                attr.tokens = quote!((#args));
+            }
+            Item::Use(item_use) => {
+                if let Some(tree) = guest_use_tree(&item_use.tree, false) {
+                    guest_items.push(Item::Use(ItemUse {
+                        tree,
+                        ..item_use.clone()
+                    }));
+                }
+            }
+            item => {
+                reject_nested_attrs(std::slice::from_ref(item))?;
+                guest_items.push(item.clone());
+            }
+        }
+    }
+
+    if let Some(out_dir) = out_dir {
+        write_unique_guest(
+            &out_dir,
+            &["remote_mod", &format!("{}.rs", name)],
+            quote!(#(#guest_items)*),
+            Some(&[]),
+            &module,
+            &source,
+        )?;
+    }
+
+    Ok(quote!(#item))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, path::PathBuf};
+    use syn::{File as SynFile, Item, ItemFn};
+
+    fn expand(attrs: TokenStream, item: TokenStream, out_dir: Option<&Path>) -> SynFile {
+        let attrs = syn::parse2(attrs).unwrap();
+        let f = syn::parse2(item).unwrap();
+        let out_dir = out_dir.map(|dir| dir.to_string_lossy().into_owned());
+        let package = PackageConfig::default();
+        syn::parse2(remote_fn_impl(attrs, f, &package, Source::default(), out_dir).unwrap())
+            .unwrap()
+    }
+
+    /// Creates an empty output dir unique to the test.
+    fn out_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("gfaas-macro-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src").join("bin")).unwrap();
+        dir
+    }
+
+    fn host_fn(file: &SynFile) -> &ItemFn {
+        file.items
+            .iter()
+            .find_map(|item| match item {
+                Item::Fn(f) => Some(f),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    /// Returns the function `name` of the companion module.
+    fn companion<'a>(file: &'a SynFile, name: &str) -> &'a ItemFn {
+        file.items
+            .iter()
+            .find_map(|item| match item {
+                Item::Mod(m) => m.content.as_ref(),
+                _ => None,
+            })
+            .and_then(|(_, items)| {
+                items.iter().find_map(|item| match item {
+                    Item::Fn(f) if f.sig.ident == name => Some(f),
+                    _ => None,
+                })
+            })
+            .unwrap()
+    }
+
+    fn to_string<T: quote::ToTokens>(tokens: T) -> String {
+        tokens.into_token_stream().to_string()
+    }
+
+    /// Returns the source of the Wasm module `module` generated into `dir`.
+    /// Returns the name of the Wasm module of the item `path` of a crate without a name.
+    fn module(path: &[&str], origin: &str) -> String {
+        mangle(&PackageConfig::default(), path, origin)
+    }
+
+    fn read_guest(dir: &Path, module: &str) -> String {
+        let guest = fs::read_to_string(dir.join("src/bin").join(format!("{}.rs", module))).unwrap();
+        to_string(syn::parse_str::<SynFile>(&guest).unwrap())
+    }
+
+    #[test]
+    fn unit_return_native() {
+        for item in [
+            quote!(
+                fn touch(path: String) {
+                    std::fs::write(path, b"").unwrap();
+                }
+            ),
+            quote!(
+                fn touch(path: String) -> () {
+                    std::fs::write(path, b"").unwrap();
+                }
+            ),
+        ]
+        .iter()
+        {
+            let file = expand(quote!(native = true), item.clone(), None);
+            let f = host_fn(&file);
+            assert!(f.sig.asyncness.is_some());
+            assert_eq!(to_string(&f.sig.inputs), "path : String");
+            assert_eq!(
+                to_string(&f.sig.output),
+                "-> std :: result :: Result < () , gfaas :: Error >"
+            );
+        }
+    }
+
+    #[test]
+    fn zero_args_native() {
+        let file = expand(
+            quote!(native = true),
+            quote!(
+                fn answer() -> u32 {
+                    42
+                }
+            ),
+            None,
+        );
+        let f = host_fn(&file);
+        assert!(f.sig.inputs.is_empty());
+        assert_eq!(
+            to_string(&f.sig.output),
+            "-> std :: result :: Result < u32 , gfaas :: Error >"
+        );
+        assert!(to_string(&f.block).contains("answer ()"));
+    }
+
+    #[test]
+    fn dispatches_without_out_dir() {
+        let file = expand(
+            quote!(backend = "mock"),
+            quote!(
+                fn answer() -> u32 {
+                    42
+                }
+            ),
+            None,
+        );
+        let body = to_string(&host_fn(&file).block);
+        assert!(body.contains("gfaas :: dispatch :: < gfaas :: backend :: Mock >"));
+        assert!(!body.contains("spawn_blocking"));
+    }
+
+    #[test]
+    fn package_defaults() {
+        let package = PackageConfig {
+            codec: Some("bincode".to_owned()),
+            subnet: Some("devnet".to_owned()),
+            budget: Some(7),
+            ..PackageConfig::default()
+        };
+        let attrs = syn::parse2(quote!(budget = 10)).unwrap();
+        let f = syn::parse2(quote!(
+            fn double(x: u32) -> u32 {
+                x * 2
+            }
+        ))
+        .unwrap();
+        let dir = out_dir("package_defaults");
+        let out = Some(dir.to_string_lossy().into_owned());
+        let file: SynFile =
+            syn::parse2(remote_fn_impl(attrs, f, &package, Source::default(), out).unwrap())
+                .unwrap();
+        let body = to_string(&host_fn(&file).block);
+        assert!(body.contains("config . budget = 10u64 ;"));
+        assert!(!body.contains("config . budget = 7u64 ;"));
+        assert!(body.contains("config . subnet = \"devnet\" . to_owned () ;"));
+        assert!(body.contains("gfaas :: codec :: Bincode"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unit_return_wasm() {
+        let dir = out_dir("unit_return_wasm");
+        let file = expand(
+            quote!(backend = "mock"),
+            quote!(
+                fn touch(path: String) {
+                    std::fs::write(path, b"").unwrap();
+                }
+            ),
+            Some(&dir),
+        );
+        let f = host_fn(&file);
+        assert_eq!(
+            to_string(&f.sig.output),
+            "-> std :: result :: Result < () , gfaas :: Error >"
+        );
+        let body = to_string(&f.block);
+        assert!(
+            body.contains("let res : () = < gfaas :: codec :: Json as gfaas :: Codec > :: decode")
+        );
+
+        let main = read_guest(&dir, &module(&["touch"], ""));
+        assert!(main.contains("let res = touch (in0) ;"));
+        assert!(main.contains("serde_json :: to_vec (& res)"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn zero_args_wasm() {
+        let dir = out_dir("zero_args_wasm");
+        let file = expand(
+            quote!(backend = "mock"),
+            quote!(
+                fn tick() {}
+            ),
+            Some(&dir),
+        );
+        let f = host_fn(&file);
+        assert!(f.sig.inputs.is_empty());
+        let body = to_string(&f.block);
+        assert!(body.contains("let inputs = vec ! [] ;"));
+
+        let main = read_guest(&dir, &module(&["tick"], ""));
+        assert!(main.contains("let res = tick () ;"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn zero_args_map() {
+        let file = expand(
+            quote!(native = true),
+            quote!(
+                fn answer() -> u32 {
+                    42
+                }
+            ),
+            None,
+        );
+        let map = companion(&file, "map");
+        let where_clause = to_string(&map.sig.generics.where_clause);
+        assert!(where_clause.contains("I : IntoIterator < Item = () >"));
+    }
+
+    #[test]
+    fn call_in() {
+        let item = quote!(
+            fn greet(mut session: String) -> String {
+                session.insert_str(0, "hi ");
+                session
+            }
+        );
+        let file = expand(quote!(native = true), item.clone(), None);
+        let call_in = companion(&file, "call_in");
+        assert_eq!(
+            to_string(&call_in.sig.inputs),
+            "__session : & gfaas :: Session , mut session : String"
+        );
+        assert!(to_string(&call_in.block).contains("(super :: greet (session)) . await"));
+
+        let dir = out_dir("call_in");
+        let file = expand(quote!(backend = "mock"), item, Some(&dir));
+        let call_in = companion(&file, "call_in");
+        assert!(to_string(&call_in.block).contains(&format!(
+            "__session . dispatch :: < gfaas :: backend :: Mock > (\"{}\"",
+            module(&["greet"], "")
+        )));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn remote_impl_wasm() {
+        let dir = out_dir("remote_impl_wasm");
+        let attrs = syn::parse2(quote!(backend = "mock")).unwrap();
+        let item = syn::parse2(quote! {
+            impl Point {
+                fn origin() -> Point { Point { x: 0.0, y: 0.0 } }
+                fn norm(&self) -> f64 { Self::square(self.x) + Self::square(self.y) }
+                #[local]
+                fn square(x: f64) -> f64 { x * x }
+            }
+        })
+        .unwrap();
+        let out_dir = Some(dir.to_string_lossy().into_owned());
+        let package = PackageConfig::default();
+        let file: SynFile = syn::parse2(
+            remote_impl_impl(attrs, item, &package, Source::default(), out_dir).unwrap(),
+        )
+        .unwrap();
+        let host = match &file.items[..] {
+            [Item::Impl(host)] => to_string(host),
+            items => panic!("unexpected items: {}", to_string(quote!(#(#items)*))),
+        };
+        assert!(host.contains("async fn origin () ->"));
+        assert!(host.contains("async fn norm (& self) ->"));
+        let point = module(&["Point"], "");
+        assert!(host.contains(&format!(
+            "dispatch :: < gfaas :: backend :: Mock > (\"{}::norm\"",
+            point
+        )));
+        assert!(host.contains("fn square (x : f64) -> f64"));
+
+        let guest = fs::read_to_string(dir.join("src/bin").join(point).join("main.rs")).unwrap();
+        let guest = to_string(syn::parse_str::<SynFile>(&guest).unwrap());
+        assert!(guest.contains("include ! (\"type.rs\")"));
+        assert!(guest.contains("let function = args . remove (1) ;"));
+        assert!(guest.contains("\"origin\" => {"));
+        assert!(guest.contains("let res = < Point > :: norm (& in0) ;"));
+        assert!(!guest.contains("\"square\""));
+        assert!(!guest.contains("# [local]"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn group_wasm() {
+        let dir = out_dir("group_wasm");
+        let double = expand(
+            quote!(group = "maths"),
+            quote!(
+                fn double(x: u32) -> u32 {
+                    x * 2
+                }
+            ),
+            Some(&dir),
+        );
+        let maths = module(&["maths"], "group");
+        assert!(to_string(&host_fn(&double).block).contains(&format!("(\"{}::double\"", maths)));
+        expand(
+            quote!(group = "maths", instantiate(u8)),
+            quote!(
+                fn ident<T>(x: T) -> T {
+                    x
+                }
+            ),
+            Some(&dir),
+        );
+
+        let group = dir.join("src/bin").join(maths);
+        let double = fs::read_to_string(group.join("double.rs")).unwrap();
+        let double = to_string(syn::parse_str::<SynFile>(&double).unwrap());
+        assert!(double.contains("pub fn __gfaas_run (mut args : Vec < String > , out : String)"));
+        assert!(!double.contains("fn main"));
+        let main = fs::read_to_string(group.join("main.rs")).unwrap();
+        let main = to_string(syn::parse_str::<SynFile>(&main).unwrap());
+        assert!(main.contains("mod double ;"));
+        assert!(main.contains("mod ident_u8 ;"));
+        assert!(main.contains("\"double\" => double :: __gfaas_run (args , out) ,"));
+        assert!(main.contains("\"ident_u8\" => ident_u8 :: __gfaas_run (args , out) ,"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn remote_mod_wasm() {
+        let dir = out_dir("remote_mod_wasm");
+        let item = syn::parse2(quote! {
+            mod shapes {
+                use gfaas::{remote_fn, RemoteFile};
+                use serde::Serialize;
+
+                #[derive(Serialize)]
+                pub struct Rect(f64, f64);
+
+                #[gfaas::remote_fn(codec = "json")]
+                pub fn area(#[raw] r: Vec<u8>) -> f64 { 0.0 }
+
+                #[remote_fn]
+                pub fn read(f: RemoteFile) {}
+            }
+        })
+        .unwrap();
+        let out_dir = Some(dir.to_string_lossy().into_owned());
+        let attrs = syn::parse2(TokenStream::new()).unwrap();
+        let source = Source {
+            location: Some("src/geo.rs:3:1".to_owned()),
+            ..Source::default()
+        };
+        let host = to_string(
+            remote_mod_impl(attrs, item, &PackageConfig::default(), source, out_dir).unwrap(),
+        );
+        let shapes = module(&["shapes"], "src/geo.rs:3:1");
+        assert!(host.contains(&format!(
+            "# [gfaas :: remote_fn (codec = \"json\" , __remote_mod = \"{}\")]",
+            shapes
+        )));
+        assert!(host.contains(&format!("# [remote_fn (__remote_mod = \"{}\")]", shapes)));
+        assert!(host.contains("use gfaas :: { remote_fn , RemoteFile } ;"));
+
+        let guest_path = dir.join("src/remote_mod").join(format!("{}.rs", shapes));
+        let guest = fs::read_to_string(guest_path).unwrap();
+        let guest = to_string(syn::parse_str::<SynFile>(&guest).unwrap());
+        assert!(guest.contains("use gfaas :: { RemoteFile } ;"));
+        assert!(guest.contains("use serde :: Serialize ;"));
+        assert!(guest.contains("pub struct Rect (f64 , f64) ;"));
+        assert!(guest.contains("pub fn area (r : Vec < u8 >) -> f64"));
+        assert!(guest.contains("pub fn read (f : RemoteFile) { }"));
+        assert!(!guest.contains("remote_fn"));
+
+        expand(
+            quote!(__remote_mod = #shapes),
+            quote!(
+                pub fn read(f: RemoteFile) {}
+            ),
+            Some(&dir),
+        );
+        let read = read_guest(&dir, &module(&["shapes", "read"], ""));
+        assert!(read.contains("mod gfaas"));
+        assert!(read.contains("mod shapes {"));
+        assert!(read.contains(&format!("\"/src/remote_mod/{}.rs\"", shapes)));
+        assert!(read.contains("shapes :: __gfaas_run (args , out) ;"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mangles_names() {
+        let dir = out_dir("mangles_names");
+        let sources = dir.join("sources");
+        fs::create_dir_all(&sources).unwrap();
+        let (a, b) = (sources.join("a.rs"), sources.join("b.rs"));
+        fs::write(&a, "mod a {}").unwrap();
+        fs::write(&b, "mod b {}").unwrap();
+        let package = PackageConfig {
+            crate_name: "app".to_owned(),
+            ..PackageConfig::default()
+        };
+        let expand = |attrs: TokenStream, file: &Path, position: &str| {
+            let attrs = syn::parse2(attrs).unwrap();
+            let f = syn::parse2(quote!(
+                fn compute(x: u32) -> u32 {
+                    x + 1
+                }
+            ))
+            .unwrap();
+            let source = Source {
+                location: Some(format!("{}:{}", file.display(), position)),
+                digest: source::digest(file),
+            };
+            let out_dir = Some(dir.to_string_lossy().into_owned());
+            remote_fn_impl(attrs, f, &package, source, out_dir).map(to_string)
+        };
+        let name = |host: &str| {
+            let start = host.find("(\"app-").unwrap() + 2;
+            host[start..start + host[start..].find('"').unwrap()].to_owned()
+        };
+        // The same functions of different modules get Wasm modules of their own, named after
+        // the function.
+        let in_a = expand(quote!(backend = "mock"), &a, "1:1").unwrap();
+        let in_b = expand(quote!(backend = "mock"), &b, "1:1").unwrap();
+        let in_a_nested = expand(quote!(backend = "mock"), &a, "9:5").unwrap();
+        let names = [name(&in_a), name(&in_b), name(&in_a_nested)];
+        assert!(names[0].starts_with("app-compute-"));
+        assert_ne!(names[0], names[1]);
+        assert_ne!(names[0], names[2]);
+        let guest = fs::read_to_string(dir.join(format!("src/bin/{}.rs", names[0]))).unwrap();
+        let header = format!("// Generated by gfaas from {}:1:1 (", a.display());
+        assert!(guest.starts_with(&header));
+        // The names are stable, so the same function is fine to expand again.
+        assert_eq!(
+            name(&expand(quote!(backend = "mock"), &a, "1:1").unwrap()),
+            names[0]
+        );
+
+        // The functions of a group are named after the function only, and so those of the same
+        // name collide as long as both are found in the sources. The group itself gets a Wasm
+        // module of its own, even if named like a function.
+        let group = quote!(backend = "mock", group = "compute");
+        let grouped = name(&expand(group.clone(), &a, "1:1").unwrap());
+        let (grouped, function) = grouped.split_once("::").unwrap();
+        assert_eq!(function, "compute");
+        assert!(grouped.starts_with("app-compute-"));
+        assert!(!names.iter().any(|name| name == grouped));
+        let err = expand(group.clone(), &a, "9:5").unwrap_err();
+        assert!(err.to_string().contains("collides"));
+        assert!(err.to_string().contains(&format!("{}:1:1", a.display())));
+        let err = expand(group.clone(), &b, "1:1").unwrap_err();
+        assert!(err.to_string().contains("collides"));
+        // The files generated for the previous versions of the sources are overwritten.
+        fs::write(&a, "mod a {\n}").unwrap();
+        expand(group.clone(), &a, "9:5").unwrap();
+        fs::remove_file(&a).unwrap();
+        expand(group, &b, "1:1").unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn renders_verbatim_bodies() {
+        let f: TokenStream = "fn f() {\n    let x = 1;\n\n    panic!(\"boom {}\", x)\n}"
+            .parse()
+            .unwrap();
+        let (source, regions) = render(quote!(mod m { #f }));
+        assert_eq!(regions.len(), 1);
+        let (first, last, origin) = regions[0];
+        assert_eq!((last - first, origin), (4, 1));
+        let lines: Vec<_> = source.lines().collect();
+        assert_eq!(lines[first - 1].trim_end(), "       {");
+        assert_eq!(lines[first + 2], "    panic!(\"boom {}\", x)");
+        assert_eq!(lines[last - 1], "}");
+        assert!(syn::parse_str::<SynFile>(&source).is_ok());
+
+        // The groups modified by the macros aren't copied.
+        let (source, regions) = render(quote!(fn f() { #f 1 }));
+        assert_eq!(regions.len(), 1);
+        assert!(source.contains("fn f () {"));
+    }
 }
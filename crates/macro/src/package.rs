@@ -0,0 +1,124 @@
+//! Settings of the package being compiled, found in its `[package.metadata.gfaas]` table.
+use proc_macro2::Span;
+use std::{env, fs, path::Path};
+
+/// Defaults of the arguments to the attributes, which apply to every function of the package,
+/// along with the name of the crate being compiled.
+#[derive(Debug, Default)]
+pub struct PackageConfig {
+    pub(crate) crate_name: String,
+    pub(crate) codec: Option<String>,
+    pub(crate) subnet: Option<String>,
+    pub(crate) budget: Option<u64>,
+}
+
+impl PackageConfig {
+    /// Loads the settings from the manifest of the package being compiled, if any.
+    pub fn load() -> syn::Result<Self> {
+        let crate_name = env::var("CARGO_CRATE_NAME")
+            .or_else(|_| env::var("CARGO_PKG_NAME").map(|name| name.replace('-', "_")))
+            .unwrap_or_default();
+        let manifest_dir = match env::var("CARGO_MANIFEST_DIR") {
+            Ok(dir) => dir,
+            Err(_) => {
+                return Ok(Self {
+                    crate_name,
+                    ..Self::default()
+                })
+            }
+        };
+        let path = Path::new(&manifest_dir).join("Cargo.toml");
+        fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| Self::parse(&contents))
+            .map(|package| Self {
+                crate_name,
+                ..package
+            })
+            .map_err(|err| {
+                syn::Error::new(
+                    Span::call_site(),
+                    format!(
+                        "reading '[package.metadata.gfaas]' of {}: {}",
+                        path.display(),
+                        err
+                    ),
+                )
+            })
+    }
+
+    fn parse(manifest: &str) -> Result<Self, String> {
+        let manifest: toml::Value = manifest.parse().map_err(|err| format!("{}", err))?;
+        let mut package = Self::default();
+        let config = match manifest
+            .get("package")
+            .and_then(|package| package.get("metadata"))
+            .and_then(|metadata| metadata.get("gfaas"))
+        {
+            Some(config) => config,
+            None => return Ok(package),
+        };
+        if let Some(codec) = config.get("codec") {
+            match codec.as_str() {
+                Some(x @ "json") | Some(x @ "bincode") | Some(x @ "cbor") | Some(x @ "msgpack") => {
+                    package.codec.replace(x.to_owned());
+                }
+                _ => {
+                    return Err(
+                        "invalid codec: expected 'json', 'bincode', 'cbor', or 'msgpack'".into(),
+                    )
+                }
+            }
+        }
+        if let Some(subnet) = config.get("subnet") {
+            let subnet = subnet.as_str().ok_or("invalid subnet: expected string")?;
+            package.subnet.replace(subnet.to_owned());
+        }
+        if let Some(budget) = config.get("budget") {
+            let budget = budget
+                .as_integer()
+                .filter(|budget| *budget >= 0)
+                .ok_or("invalid budget: expected a non-negative integer")?;
+            package.budget.replace(budget as u64);
+        }
+        Ok(package)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_metadata() {
+        let package = PackageConfig::parse(
+            r#"
+            [package]
+            name = "app"
+
+            [package.metadata.gfaas]
+            codec = "bincode"
+            subnet = "devnet"
+            budget = 10
+            dependencies = { log = "0.4" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(package.codec.as_deref(), Some("bincode"));
+        assert_eq!(package.subnet.as_deref(), Some("devnet"));
+        assert_eq!(package.budget, Some(10));
+
+        let package = PackageConfig::parse("[package]\nname = \"app\"").unwrap();
+        assert!(package.codec.is_none() && package.subnet.is_none() && package.budget.is_none());
+
+        for invalid in &[
+            "codec = \"yaml\"",
+            "subnet = 1",
+            "budget = -1",
+            "budget = \"10\"",
+        ] {
+            let manifest = format!("[package.metadata.gfaas]\n{}", invalid);
+            assert!(PackageConfig::parse(&manifest).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Whereabouts of the item being expanded within the sources of the crate being compiled.
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Location of the item being expanded, which macros don't get to see by means of `file!()`,
+/// along with the digest of its file, which tells apart the versions of the file.
+#[derive(Debug, Default)]
+pub struct Source {
+    /// File of the item relative to the package, along with its line and column, if known.
+    pub(crate) location: Option<String>,
+    /// Digest of the contents of the file of the item, if known.
+    pub(crate) digest: Option<u64>,
+}
+
+impl Source {
+    /// Returns the file of the item relative to the package, if known.
+    pub(crate) fn file(&self) -> Option<&str> {
+        self.location.as_deref()?.rsplitn(3, ':').nth(2)
+    }
+
+    /// Locates the item from the span of the macro invocation. The file of the span is only
+    /// known since Rust 1.88, so the location is unknown with older compilers, as it is in
+    /// IDEs, or for the items generated by other procedural macros.
+    pub fn locate() -> Self {
+        let span = proc_macro2::Span::call_site();
+        let file = match span.local_file() {
+            Some(file) => file,
+            None => return Self::default(),
+        };
+        // Relative paths are relative to the dir rustc is run from, i.e., the workspace root.
+        let file = env::current_dir()
+            .map(|dir| dir.join(&file))
+            .unwrap_or(file);
+        let file = fs::canonicalize(&file).unwrap_or(file);
+        let manifest_dir = manifest_dir();
+        let relative = file.strip_prefix(&manifest_dir).unwrap_or(&file);
+        let start = span.start();
+        Self {
+            location: Some(format!(
+                "{}:{}:{}",
+                relative.display(),
+                start.line,
+                start.column + 1
+            )),
+            digest: digest(&file),
+        }
+    }
+}
+
+/// Returns the dir of the package being compiled.
+fn manifest_dir() -> PathBuf {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    fs::canonicalize(&manifest_dir).unwrap_or(manifest_dir)
+}
+
+/// Returns the digest of the contents of the source `file`, relative to the package unless
+/// absolute, if the file is found.
+pub(crate) fn digest(file: &Path) -> Option<u64> {
+    fs::read(manifest_dir().join(file))
+        .ok()
+        .map(|contents| stable_hash(&contents))
+}
+
+/// Returns the 64-bit FNV-1a hash of `bytes`, which unlike the hashers of std is stable across
+/// Rust versions and builds.
+pub(crate) fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digests_files() {
+        let dir = env::temp_dir().join(format!("gfaas-macro-{}-source", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("lib.rs");
+        fs::write(&file, "fn a() {}").unwrap();
+        let before = digest(&file);
+        assert!(before.is_some());
+        assert_eq!(digest(&file), before);
+        fs::write(&file, "fn b() {}").unwrap();
+        assert_ne!(digest(&file), before);
+        assert_eq!(digest(&dir.join("main.rs")), None);
+        fs::remove_dir_all(dir).unwrap();
+
+        // The hash is the one of FNV-1a.
+        assert_eq!(stable_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(stable_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -16,7 +16,8 @@ use std::{
 use structopt::StructOpt;
 
 #[remote_fn(budget = 1000, timeout = 900, subnet = "devnet-alpha.2")]
-fn generate_proof_on_golem(params: Vec<u8>, preimage: Vec<u8>) -> Vec<u8> {
+#[raw]
+fn generate_proof_on_golem(#[raw] params: Vec<u8>, preimage: Vec<u8>) -> Vec<u8> {
     use bellman::{
         gadgets::{
             boolean::{AllocatedBit, Boolean},
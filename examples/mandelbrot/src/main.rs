@@ -1,10 +1,6 @@
 use anyhow::Result;
-use futures::{
-    lock::Mutex,
-    stream::{self, TryStreamExt},
-};
 use gfaas::remote_fn;
-use std::{fs::File, io::BufWriter, sync::Arc};
+use std::{fs::File, io::BufWriter};
 use structopt::StructOpt;
 
 #[remote_fn(budget = 1000, timeout = 900, subnet = "devnet-alpha.2")]
@@ -74,7 +70,7 @@ async fn main() -> Result<()> {
     let width = opts.width;
     let height = opts.height;
 
-    let mut chunks: Vec<Result<_>> = vec![];
+    let mut chunks = vec![];
     for n in 0..opts.in_parallel {
         let start_y = n * max_row_size;
         let end_y = if start_y + max_row_size > height {
@@ -82,21 +78,10 @@ async fn main() -> Result<()> {
         } else {
             start_y + max_row_size
         };
-        chunks.push(Ok((n, start_y, end_y)));
+        chunks.push((start_y, end_y, width, height));
     }
 
-    let output = Arc::new(Mutex::new(Vec::new()));
-    let chunks = stream::iter(chunks);
-    chunks
-        .try_for_each_concurrent(MAX_CONCURRENT_JOBS, |(n, start_y, end_y)| {
-            let output = Arc::clone(&output);
-            async move {
-                let rect = compute_rectangle(start_y, end_y, width, height).await?;
-                output.lock().await.push((n, rect));
-                Ok(())
-            }
-        })
-        .await?;
+    let output = compute_rectangle::map(chunks, MAX_CONCURRENT_JOBS).await?;
 
     let file = File::create("mandelbrot.png")?;
     let mut w = BufWriter::new(file);
@@ -106,13 +91,8 @@ async fn main() -> Result<()> {
     encoder.set_depth(png::BitDepth::Eight);
     let mut writer = encoder.write_header()?;
 
-    let output = Arc::try_unwrap(output)
-        .expect("container with computation results should be fully filled in by now");
-    let mut output = output.into_inner();
-    output.sort_by(|(x1, _), (x2, _)| x1.cmp(x2));
     let output: Vec<_> = output
         .into_iter()
-        .map(|(_, c)| c)
         .flatten()
         .map(|c| (MAX_ITER - c) as u8)
         .collect();
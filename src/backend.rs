@@ -0,0 +1,692 @@
+//! Backends capable of executing `gfaas::remote_fn`-annotated functions.
+//!
+//! Every call to an expanded `gfaas::remote_fn` goes through [`dispatch`] which drives
+//! a [`Backend`] through the following steps:
+//!
+//! 1. [`Backend::prepare`] the package containing the Wasm module,
+//! 2. [`Backend::upload`] each serialized input argument, or [`Backend::upload_file`] each
+//!    file passed as is,
+//! 3. [`Backend::run`] the module's entry point,
+//! 4. [`Backend::download`] the serialized output.
+//!
+//! This crate ships with the following backends: [`Local`] which executes the module on your
+//! machine using an embedded Yagna WASI runtime, [`Embedded`] which instantiates the module
+//! in-process skipping the Yagna packaging altogether, [`Yagna`] which distributes the module
+//! on the Golem Network, and [`Mock`] which never leaves the process and instead invokes
+//! handlers registered for the functions. You are free to implement your own backend though;
+//! for instance, a local process pool.
+//!
+//! ## Selecting the backend at runtime
+//!
+//! The backend used by an expanded function is selected on every call in the following order:
+//!
+//! 1. the backend named by the `GFAAS_BACKEND` env variable, i.e., `embedded`, `local`, `yagna`
+//!    or `mock`,
+//! 2. the backend of the [`Session`] the function is called in, if any,
+//! 3. the backend set with [`set_default_backend`],
+//! 4. the backend specified with the `backend` (or `run_local`) argument to `gfaas::remote_fn`,
+//! 5. [`Yagna`] backend.
+//!
+//! [`dispatch`]: fn.dispatch.html
+//! [`Backend`]: trait.Backend.html
+//! [`Backend::prepare`]: trait.Backend.html#tymethod.prepare
+//! [`Backend::upload`]: trait.Backend.html#tymethod.upload
+//! [`Backend::upload_file`]: trait.Backend.html#method.upload_file
+//! [`Backend::run`]: trait.Backend.html#tymethod.run
+//! [`Backend::download`]: trait.Backend.html#tymethod.download
+//! [`Local`]: struct.Local.html
+//! [`Embedded`]: struct.Embedded.html
+//! [`Yagna`]: struct.Yagna.html
+//! [`Mock`]: struct.Mock.html
+//! [`set_default_backend`]: fn.set_default_backend.html
+//! [`Session`]: ../struct.Session.html
+
+mod embedded;
+mod local;
+mod mock;
+mod yagna;
+
+pub use embedded::Embedded;
+pub use local::Local;
+pub use mock::Mock;
+pub use yagna::Yagna;
+
+use crate::{
+    retry::{before_retry, default_retry_policy, RetryPolicy},
+    Error,
+};
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::Duration,
+};
+use tokio::task;
+
+/// Configuration of a single invocation of a remote function.
+///
+/// The values are set via the arguments to `gfaas::remote_fn` attribute. It is up to the
+/// [`Backend`] which, if any, of these are meaningful.
+///
+/// [`Backend`]: trait.Backend.html
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Maximum budget in NGNT (defaults to 100).
+    pub budget: u64,
+    /// Timeout of the computation (defaults to 10 minutes).
+    pub timeout: Duration,
+    /// Subnet tag (defaults to "devnet-alpha.2").
+    pub subnet: String,
+    /// Policy of retrying failed invocations (defaults to the policy set with
+    /// [`set_default_retry_policy`], or no retries).
+    ///
+    /// [`set_default_retry_policy`]: ../fn.set_default_retry_policy.html
+    pub retry: RetryPolicy,
+    /// Node ids of the providers which must not run the function.
+    pub excluded_providers: Vec<String>,
+    /// Memory the provider has to offer, in GiB: more than this (defaults to 0.5).
+    pub min_mem_gib: f64,
+    /// Storage the provider has to offer, in GiB: more than this (defaults to 1).
+    pub min_storage_gib: f64,
+    /// Minimum number of CPU threads the provider has to offer (defaults to none).
+    pub min_cpu_threads: Option<u32>,
+    /// Additional constraints on the provider, all of which have to be satisfied, given in the
+    /// syntax of Golem's demands, e.g., `(golem.inf.cpu.architecture=x86_64)`.
+    pub constraints: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            budget: 100,
+            timeout: Duration::from_secs(10 * 60),
+            subnet: "devnet-alpha.2".to_owned(),
+            retry: default_retry_policy(),
+            excluded_providers: vec![],
+            min_mem_gib: 0.5,
+            min_storage_gib: 1.0,
+            min_cpu_threads: None,
+            constraints: vec![],
+        }
+    }
+}
+
+/// Input argument of a remote function.
+#[derive(Debug, Clone)]
+pub enum Input {
+    /// Serialized value, uploaded with [`Backend::upload`].
+    ///
+    /// [`Backend::upload`]: trait.Backend.html#tymethod.upload
+    Data(Vec<u8>),
+    /// Local file, uploaded with [`Backend::upload_file`].
+    ///
+    /// [`Backend::upload_file`]: trait.Backend.html#method.upload_file
+    File(PathBuf),
+    /// Local directory, every file of which is uploaded with [`Backend::upload_file`].
+    ///
+    /// [`Backend::upload_file`]: trait.Backend.html#method.upload_file
+    Dir(PathBuf),
+}
+
+impl From<Vec<u8>> for Input {
+    fn from(data: Vec<u8>) -> Self {
+        Input::Data(data)
+    }
+}
+
+/// Interface which every backend executing remote functions has to implement.
+///
+/// A new instance of the backend is created for every invocation of the remote function, and
+/// its methods are called exactly once each and in order, with the exception of [`upload`]
+/// and [`upload_file`] which are called once per input argument (or file thereof). Paths
+/// inside the module's sandbox are always relative to the `/workdir` directory.
+///
+/// When a remote function is invoked on a batch of inputs instead, [`prepare`] is followed
+/// by a single call to [`run_batch`].
+///
+/// Within a [`Session`] on the other hand, the instance prepared for a module is kept for
+/// the lifetime of the session, and invoked repeatedly: every invocation ends with
+/// a call to [`reset`].
+///
+/// Either way, the instance gets to [`release`] whatever it holds on to once it's no longer
+/// needed.
+///
+/// [`upload`]: #tymethod.upload
+/// [`upload_file`]: #method.upload_file
+/// [`prepare`]: #tymethod.prepare
+/// [`run_batch`]: #method.run_batch
+/// [`Session`]: ../struct.Session.html
+/// [`reset`]: #method.reset
+/// [`release`]: #method.release
+#[async_trait(?Send)]
+pub trait Backend {
+    /// Creates a new instance of the backend for a single invocation of a remote function.
+    fn new(config: &Config) -> Result<Self, Error>
+    where
+        Self: Sized;
+
+    /// Prepares the package containing the Wasm module found at `module`.
+    async fn prepare(&mut self, module: &Path) -> Result<(), Error>;
+
+    /// Uploads the input `data` so that it is available to the module as `/workdir/<name>`.
+    async fn upload(&mut self, name: &str, data: Vec<u8>) -> Result<(), Error>;
+
+    /// Uploads the local file found at `path` so that it is available to the module as
+    /// `/workdir/<name>`. When uploading the contents of a directory, `name` contains `/`
+    /// separated subdirectories which are expected to be created as needed.
+    ///
+    /// The default implementation reads the file into memory, and passes it to [`upload`].
+    ///
+    /// [`upload`]: #tymethod.upload
+    async fn upload_file(&mut self, name: &str, path: &Path) -> Result<(), Error> {
+        let data =
+            fs::read(path).with_context(|| format!("reading input file '{}'", path.display()))?;
+        self.upload(name, data).await
+    }
+
+    /// Runs the `entry_point` of the prepared package with `args`, i.e., the paths to the
+    /// inputs followed by the path to the output, preceded by the name of the function to run
+    /// if the module hosts several of them.
+    ///
+    /// Once this method returns, the files named in `outputs` are expected to be available
+    /// for [`download`].
+    ///
+    /// [`download`]: #tymethod.download
+    async fn run(
+        &mut self,
+        entry_point: &str,
+        args: Vec<String>,
+        outputs: &[&str],
+    ) -> Result<(), Error>;
+
+    /// Downloads the contents of `/workdir/<name>` produced by the module.
+    async fn download(&mut self, name: &str) -> Result<Vec<u8>, Error>;
+
+    /// Takes whatever the module wrote to its standard error during the last [`run`], so that
+    /// it can be reported should the module panic. The default implementation captures
+    /// nothing.
+    ///
+    /// [`run`]: #tymethod.run
+    fn take_stderr(&mut self) -> Option<String> {
+        None
+    }
+
+    /// Returns the node ids of the providers which failed to run the function, so that they can
+    /// be excluded from the subsequent attempts with [`Config::excluded_providers`]. The
+    /// default implementation returns none.
+    ///
+    /// [`Config::excluded_providers`]: struct.Config.html#structfield.excluded_providers
+    fn failed_providers(&mut self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Discards the inputs and outputs of the last invocation, so that the instance can be
+    /// reused for the next one within a [`Session`]. The default implementation does nothing.
+    ///
+    /// [`Session`]: ../struct.Session.html
+    async fn reset(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Releases whatever the instance keeps between the invocations, e.g., the agreement with
+    /// a provider. It is called once the instance is done with, i.e., after every attempt
+    /// outside of a [`Session`], or once the session is closed or gives up on the instance.
+    /// The default implementation does nothing.
+    ///
+    /// [`Session`]: ../struct.Session.html
+    async fn release(&mut self) {}
+
+    /// Runs the `entry_point` of the prepared package once for every element of `tasks`,
+    /// and returns the serialized outputs in the same order. Each task consists of the
+    /// inputs to the `function` of the module, if the module hosts several of them.
+    ///
+    /// `concurrency` is only an upper bound on the number of tasks running at the same time,
+    /// and backends are free to run fewer of them. The default implementation runs the tasks
+    /// one after another with [`upload`], [`run`] and [`download`], calling [`reset`] before
+    /// each of them. It stops at the first task which panics, as the batch fails anyway, so
+    /// that [`take_stderr`] returns whatever that task wrote. Backends which can do better
+    /// should override it.
+    ///
+    /// [`upload`]: #tymethod.upload
+    /// [`run`]: #tymethod.run
+    /// [`download`]: #tymethod.download
+    /// [`reset`]: #method.reset
+    /// [`take_stderr`]: #method.take_stderr
+    async fn run_batch(
+        &mut self,
+        entry_point: &str,
+        function: Option<&str>,
+        tasks: Vec<Vec<Input>>,
+        concurrency: usize,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        let _ = concurrency;
+        let mut outputs = Vec::with_capacity(tasks.len());
+        for inputs in tasks {
+            self.reset().await?;
+            let output = invoke(self, entry_point, function, inputs).await?;
+            let panicked = output.first() == Some(&PANIC_TAG);
+            outputs.push(output);
+            if panicked {
+                break;
+            }
+        }
+        Ok(outputs)
+    }
+}
+
+/// Name of the env variable which selects the backend at runtime.
+pub const BACKEND_ENV_VAR: &str = "GFAAS_BACKEND";
+
+pub(crate) type Factory = fn(&Config) -> Result<Box<dyn Backend>, Error>;
+
+static DEFAULT_BACKEND: RwLock<Option<Factory>> = RwLock::new(None);
+
+pub(crate) fn create<B: Backend + 'static>(config: &Config) -> Result<Box<dyn Backend>, Error> {
+    Ok(Box::new(B::new(config)?))
+}
+
+/// Sets the backend `B` as the default for all remote functions, overriding the backend
+/// specified in the `gfaas::remote_fn` attribute.
+///
+/// Note that the backend named by the `GFAAS_BACKEND` env variable, if any, still takes
+/// precedence.
+pub fn set_default_backend<B: Backend + 'static>() {
+    *DEFAULT_BACKEND.write().unwrap() = Some(create::<B>);
+}
+
+/// Selects the backend to use in place of `B`, with the `preferred` one, e.g., the backend of
+/// a session, taking precedence over the default backend but not over the env variable.
+pub(crate) fn select<B: Backend + 'static>(preferred: Option<Factory>) -> Result<Factory, Error> {
+    if let Some(name) = env::var(BACKEND_ENV_VAR).ok().filter(|x| !x.is_empty()) {
+        let factory: Factory = match name.as_str() {
+            "embedded" => create::<Embedded>,
+            "local" => create::<Local>,
+            "yagna" => create::<Yagna>,
+            "mock" => create::<Mock>,
+            x => {
+                return Err(anyhow!(
+                "unknown backend '{}' set in {}: expected 'embedded', 'local', 'yagna', or 'mock'",
+                x,
+                BACKEND_ENV_VAR
+            )
+                .into())
+            }
+        };
+        return Ok(factory);
+    }
+    if let Some(factory) = preferred.or(*DEFAULT_BACKEND.read().unwrap()) {
+        return Ok(factory);
+    }
+    Ok(create::<B>)
+}
+
+/// Tag prepended by the Wasm module to the output file when the function returned normally.
+pub(crate) const OUTPUT_TAG: u8 = 0;
+/// Tag prepended by the Wasm module to the output file when the function panicked. It is
+/// followed by the failure record written by the panic hook.
+const PANIC_TAG: u8 = 1;
+
+pub(crate) fn unpack_output(mut data: Vec<u8>, stderr: Option<String>) -> Result<Vec<u8>, Error> {
+    match data.first() {
+        Some(&OUTPUT_TAG) => {
+            data.remove(0);
+            Ok(data)
+        }
+        Some(&PANIC_TAG) => Err(Error::from_panic_record(&data[1..], stderr)),
+        _ => Err(Error::Serialization(anyhow!("malformed output data"))),
+    }
+}
+
+/// Fails with [`Error::MissingModule`] unless the Wasm `module` exists.
+///
+/// [`Error::MissingModule`]: ../enum.Error.html#variant.MissingModule
+fn ensure_module_exists(module: &Path) -> Result<(), Error> {
+    if !module.exists() {
+        return Err(Error::MissingModule(module.to_owned()));
+    }
+    Ok(())
+}
+
+/// Runs the blocking function `f` on a thread pool.
+async fn spawn_blocking<F, T>(f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    task::spawn_blocking(f)
+        .await
+        .context("joining blocking task")?
+}
+
+/// Hard-links the file `from` to `to` creating the parent directories as needed, or copies it
+/// if linking fails, e.g., because the two are on different filesystems.
+fn link_or_copy(from: &Path, to: &Path) -> Result<(), Error> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).context("creating input dir")?;
+    }
+    fs::hard_link(from, to)
+        .or_else(|_| fs::copy(from, to).map(|_| ()))
+        .with_context(|| format!("linking input file '{}'", from.display()))?;
+    Ok(())
+}
+
+/// Removes all files and directories found in `dir`, except for the top-level ones named in
+/// `keep`.
+fn clear_dir(dir: &Path, keep: &[&str]) -> Result<(), Error> {
+    let entries =
+        fs::read_dir(dir).with_context(|| format!("reading workdir '{}'", dir.display()))?;
+    for entry in entries {
+        let entry = entry.context("reading workdir entry")?;
+        if keep.iter().any(|name| entry.file_name() == **name) {
+            continue;
+        }
+        let path = entry.path();
+        let res = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        res.with_context(|| format!("removing '{}'", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Lists all files found in `dir` and its subdirectories, as pairs of their `/` separated
+/// paths relative to `dir` and full paths.
+fn list_files(dir: &Path) -> Result<Vec<(String, PathBuf)>, Error> {
+    let mut files = vec![];
+    let mut dirs = vec![(String::new(), dir.to_owned())];
+    while let Some((prefix, dir)) = dirs.pop() {
+        let entries =
+            fs::read_dir(&dir).with_context(|| format!("reading input dir '{}'", dir.display()))?;
+        for entry in entries {
+            let entry = entry.context("reading input dir entry")?;
+            let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push((format!("{}/", name), path));
+            } else {
+                files.push((name, path));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Uploads the `input` so that it is available to the module as `/workdir/<name>`.
+async fn upload_input<B: Backend + ?Sized>(
+    backend: &mut B,
+    name: &str,
+    input: Input,
+) -> Result<(), Error> {
+    match input {
+        Input::Data(data) => backend.upload(name, data).await,
+        Input::File(path) => backend.upload_file(name, &path).await,
+        Input::Dir(path) => {
+            for (file_name, path) in list_files(&path)? {
+                backend
+                    .upload_file(&format!("{}/{}", name, file_name), &path)
+                    .await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+pub(crate) fn module_path(name: &str) -> Result<PathBuf, Error> {
+    let exe_path = env::current_exe().context("extracting path to the current exe")?;
+    let parent = exe_path.parent().ok_or_else(|| {
+        anyhow!(
+            "path to the current exe without parent: '{}'",
+            exe_path.display()
+        )
+    })?;
+    Ok(parent.join(format!("{}.wasm", name)))
+}
+
+/// Splits the name of the remote function into the name of its Wasm module, and the name of
+/// the function within the module if the module hosts several of them, e.g., `Point` and
+/// `norm` in case of `Point::norm`.
+pub(crate) fn split_name(name: &str) -> (&str, Option<&str>) {
+    match name.rfind("::") {
+        Some(pos) => (&name[..pos], Some(&name[pos + 2..])),
+        None => (name, None),
+    }
+}
+
+pub(crate) async fn invoke<B: Backend + ?Sized>(
+    backend: &mut B,
+    entry_point: &str,
+    function: Option<&str>,
+    inputs: Vec<Input>,
+) -> Result<Vec<u8>, Error> {
+    let mut args = Vec::with_capacity(inputs.len() + 2);
+    // Modules hosting several functions expect the name of the one to run first.
+    args.extend(function.map(str::to_owned));
+    for (i, input) in inputs.into_iter().enumerate() {
+        let input_name = format!("in{}", i);
+        args.push(format!("/workdir/{}", input_name));
+        upload_input(backend, &input_name, input).await?;
+    }
+    args.push("/workdir/out".to_owned());
+
+    backend.run(entry_point, args, &["out"]).await?;
+    backend.download("out").await
+}
+
+/// Executes the remote function `name` using the backend `B`, unless a different backend was
+/// selected at runtime (see [module-level docs](index.html) for details).
+///
+/// This function is what every `gfaas::remote_fn`-annotated function expands into. `inputs`
+/// are the arguments to the function in order, and the returned value is the serialized
+/// output of the function. The Wasm module is expected to be found next to the
+/// current executable as `<name>.wasm`, or as `<module>.wasm` in case of `<module>::<function>`
+/// names of the functions sharing a module, i.e., the functions of a `gfaas::remote_impl`
+/// block, or of a `group`.
+pub async fn dispatch<B: Backend + 'static>(
+    name: &str,
+    config: &Config,
+    inputs: Vec<Input>,
+) -> Result<Vec<u8>, Error> {
+    let (module, function) = split_name(name);
+    let wasm = module_path(module)?;
+    let factory = select::<B>(None)?;
+    let mut config = config.clone();
+    let mut retry = 0;
+    loop {
+        let mut backend = factory(&config)?;
+        let res = async {
+            backend.prepare(&wasm).await?;
+            let output = invoke(&mut *backend, module, function, inputs.clone()).await?;
+            unpack_output(output, backend.take_stderr())
+        }
+        .await;
+        backend.release().await;
+        match res {
+            Err(err) if before_retry(&mut config, &mut *backend, &err, retry).await => retry += 1,
+            res => return res,
+        }
+    }
+}
+
+/// Executes the remote function `name` once for every element of `tasks` using the backend
+/// `B`, unless a different backend was selected at runtime.
+///
+/// This function is what the `map` companion of every `gfaas::remote_fn`-annotated function
+/// expands into. Each task consists of the arguments to the function, and the
+/// serialized outputs are returned in the same order as `tasks`. At most `concurrency` tasks
+/// are run at the same time, although the backend may run fewer, e.g., one at a time.
+///
+/// Should the function panic on any of the tasks, the whole batch fails with the
+/// [`Error::GuestPanic`] of the first task to panic.
+///
+/// [`Error::GuestPanic`]: ../enum.Error.html#variant.GuestPanic
+pub async fn dispatch_batch<B: Backend + 'static>(
+    name: &str,
+    config: &Config,
+    tasks: Vec<Vec<Input>>,
+    concurrency: usize,
+) -> Result<Vec<Vec<u8>>, Error> {
+    if tasks.is_empty() {
+        return Ok(vec![]);
+    }
+    let (module, function) = split_name(name);
+    let wasm = module_path(module)?;
+    let factory = select::<B>(None)?;
+    let mut config = config.clone();
+    let mut retry = 0;
+    loop {
+        let mut backend = factory(&config)?;
+        let res = async {
+            backend.prepare(&wasm).await?;
+            let outputs = backend
+                .run_batch(module, function, tasks.clone(), concurrency.max(1))
+                .await?;
+            let mut unpacked = Vec::with_capacity(outputs.len());
+            for output in outputs {
+                let stderr = match output.first() {
+                    Some(&PANIC_TAG) => backend.take_stderr(),
+                    _ => None,
+                };
+                unpacked.push(unpack_output(output, stderr)?);
+            }
+            Ok(unpacked)
+        }
+        .await;
+        backend.release().await;
+        match res {
+            Err(err) if before_retry(&mut config, &mut *backend, &err, retry).await => retry += 1,
+            res => return res,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn splits_name() {
+        assert_eq!(split_name("app-hello"), ("app-hello", None));
+        assert_eq!(split_name("app-Point::norm"), ("app-Point", Some("norm")));
+        // Only the last segment names the function.
+        assert_eq!(split_name("a::b::c"), ("a::b", Some("c")));
+        assert_eq!(split_name("app-group::"), ("app-group", Some("")));
+        assert_eq!(split_name(""), ("", None));
+    }
+
+    #[test]
+    fn unpacks_output() {
+        let output = unpack_output(vec![OUTPUT_TAG, 4, 2], None).unwrap();
+        assert_eq!(output, vec![4, 2]);
+        let output = unpack_output(vec![OUTPUT_TAG], None).unwrap();
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn unpacks_panic() {
+        let mut data = vec![PANIC_TAG];
+        data.extend_from_slice(br#"{"message":"boom"}"#);
+        match unpack_output(data, Some("stderr".to_owned())) {
+            Err(Error::GuestPanic {
+                message, stderr, ..
+            }) => {
+                assert_eq!(message, "boom");
+                assert_eq!(stderr.as_deref(), Some("stderr"));
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    static BATCH_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Backend panicking on the tasks whose input is `[1]`.
+    #[derive(Default)]
+    struct Panicking {
+        input: Vec<u8>,
+        stderr: Option<String>,
+    }
+
+    #[async_trait(?Send)]
+    impl Backend for Panicking {
+        fn new(_config: &Config) -> Result<Self, Error> {
+            Ok(Self::default())
+        }
+
+        async fn prepare(&mut self, _module: &Path) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn upload(&mut self, _name: &str, data: Vec<u8>) -> Result<(), Error> {
+            self.input = data;
+            Ok(())
+        }
+
+        async fn run(
+            &mut self,
+            _entry_point: &str,
+            _args: Vec<String>,
+            _outputs: &[&str],
+        ) -> Result<(), Error> {
+            let run = BATCH_RUNS.fetch_add(1, Ordering::SeqCst);
+            self.stderr = Some(format!("run {}", run));
+            Ok(())
+        }
+
+        async fn download(&mut self, _name: &str) -> Result<Vec<u8>, Error> {
+            let mut output = vec![];
+            if self.input == [1] {
+                output.push(PANIC_TAG);
+                output.extend_from_slice(br#"{"message":"boom"}"#);
+            } else {
+                output.push(OUTPUT_TAG);
+                output.extend_from_slice(&self.input);
+            }
+            Ok(output)
+        }
+
+        fn take_stderr(&mut self) -> Option<String> {
+            self.stderr.take()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn fails_batch_on_first_panic() {
+        let tasks = [[0], [1], [2], [1]]
+            .iter()
+            .map(|input| vec![Input::Data(input.to_vec())])
+            .collect();
+        let config = Config::default();
+        match dispatch_batch::<Panicking>("app-a", &config, tasks, 1).await {
+            Err(Error::GuestPanic {
+                message, stderr, ..
+            }) => {
+                assert_eq!(message, "boom");
+                assert_eq!(stderr.as_deref(), Some("run 1"));
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        // The tasks following the one which panicked are not run.
+        assert_eq!(BATCH_RUNS.load(Ordering::SeqCst), 2);
+
+        let tasks = vec![vec![Input::Data(vec![0])], vec![Input::Data(vec![2])]];
+        let outputs = dispatch_batch::<Panicking>("app-a", &config, tasks, 1)
+            .await
+            .unwrap();
+        assert_eq!(outputs, vec![vec![0], vec![2]]);
+    }
+
+    #[test]
+    fn rejects_malformed_output() {
+        for data in [vec![], vec![2, 4, 2], vec![0xff]] {
+            match unpack_output(data, None) {
+                Err(Error::Serialization(_)) => {}
+                res => panic!("unexpected result: {:?}", res),
+            }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use super::{clear_dir, ensure_module_exists, link_or_copy, spawn_blocking, Backend, Config};
+use crate::Error;
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tempfile::{tempdir, tempfile, TempDir};
+use wasi_common::{preopen_dir, WasiCtxBuilder};
+use wasmtime::{Engine, Linker, Module, Store};
+use wasmtime_wasi::Wasi;
+
+/// Compiled Wasm modules keyed by their path, shared by all instances of the backend.
+static MODULES: Mutex<Option<(Engine, BTreeMap<PathBuf, Module>)>> = Mutex::new(None);
+
+fn load_module(path: &Path) -> Result<Module, Error> {
+    let mut modules = MODULES.lock().unwrap();
+    let (engine, cache) = modules.get_or_insert_with(|| (Engine::default(), BTreeMap::new()));
+    if let Some(module) = cache.get(path) {
+        return Ok(module.clone());
+    }
+    let module = Module::from_file(engine, path)
+        .with_context(|| format!("compiling Wasm module '{}'", path.display()))?;
+    cache.insert(path.to_owned(), module.clone());
+    Ok(module)
+}
+
+/// Backend executing the Wasm module in-process using an embedded WASI runtime.
+///
+/// Unlike [`Local`], this backend doesn't build and deploy a Yagna package on every call.
+/// Instead, the module is compiled once, cached for the lifetime of the process, and
+/// instantiated directly with the inputs and outputs mapped to a temporary directory
+/// preopened as `/workdir`. This makes it the fastest way of running your functions locally,
+/// e.g., in a test suite which calls them thousands of times.
+///
+/// The standard error of the module is captured, so that it can be reported should the
+/// module panic, and then forwarded to the standard error of the process.
+///
+/// [`Local`]: struct.Local.html
+pub struct Embedded {
+    workdir: TempDir,
+    module: Option<Module>,
+    stderr: Option<String>,
+}
+
+#[async_trait(?Send)]
+impl Backend for Embedded {
+    fn new(_config: &Config) -> Result<Self, Error> {
+        let workdir = tempdir().context("creating temp dir")?;
+        Ok(Self {
+            workdir,
+            module: None,
+            stderr: None,
+        })
+    }
+
+    async fn prepare(&mut self, module: &Path) -> Result<(), Error> {
+        ensure_module_exists(module)?;
+        let path = module.to_owned();
+        let module = spawn_blocking(move || load_module(&path)).await?;
+        self.module = Some(module);
+        Ok(())
+    }
+
+    async fn upload(&mut self, name: &str, data: Vec<u8>) -> Result<(), Error> {
+        let input_path = self.workdir.path().join(name);
+        fs::write(&input_path, data).context("writing serialized data to file")?;
+        Ok(())
+    }
+
+    async fn upload_file(&mut self, name: &str, path: &Path) -> Result<(), Error> {
+        link_or_copy(path, &self.workdir.path().join(name))
+    }
+
+    async fn run(
+        &mut self,
+        entry_point: &str,
+        args: Vec<String>,
+        _outputs: &[&str],
+    ) -> Result<(), Error> {
+        let module = self
+            .module
+            .clone()
+            .ok_or_else(|| anyhow!("Wasm module was not prepared yet"))?;
+        let workdir = self.workdir.path().to_owned();
+        let args: Vec<_> = std::iter::once(entry_point.to_owned())
+            .chain(args)
+            .collect();
+        let stderr = spawn_blocking(move || instantiate_and_run(&module, &workdir, &args)).await?;
+        eprint!("{}", stderr);
+        self.stderr = Some(stderr);
+        Ok(())
+    }
+
+    async fn download(&mut self, name: &str) -> Result<Vec<u8>, Error> {
+        let output_path = self.workdir.path().join(name);
+        let output_data = fs::read(output_path).context("reading output data from file")?;
+        Ok(output_data)
+    }
+
+    fn take_stderr(&mut self) -> Option<String> {
+        self.stderr.take()
+    }
+
+    async fn reset(&mut self) -> Result<(), Error> {
+        self.stderr = None;
+        clear_dir(self.workdir.path(), &[])
+    }
+}
+
+/// Runs the module to completion, and returns whatever it wrote to its standard error.
+fn instantiate_and_run(module: &Module, workdir: &Path, args: &[String]) -> Result<String, Error> {
+    let store = Store::new(module.engine());
+    let mut linker = Linker::new(&store);
+    let mut stderr = tempfile().context("creating temp file for stderr")?;
+
+    // Add snapshot1 of WASI ABI
+    let cx = WasiCtxBuilder::new()
+        .inherit_stdio()
+        .stderr(stderr.try_clone().context("duplicating stderr handle")?)
+        .args(args)
+        .preopened_dir(
+            preopen_dir(workdir).context("preopening workdir")?,
+            "/workdir",
+        )
+        .build()
+        .context("building WASI context")?;
+    Wasi::new(&store, cx).add_to_linker(&mut linker)?;
+
+    // Add snapshot0 of WASI ABI
+    let cx = wasi_common::old::snapshot_0::WasiCtxBuilder::new()
+        .inherit_stdio()
+        .stderr(stderr.try_clone().context("duplicating stderr handle")?)
+        .args(args)
+        .preopened_dir(
+            preopen_dir(workdir).context("preopening workdir")?,
+            "/workdir",
+        )
+        .build()
+        .context("building WASI context")?;
+    wasmtime_wasi::old::snapshot_0::Wasi::new(&store, cx).add_to_linker(&mut linker)?;
+
+    linker
+        .module("", module)
+        .context("instantiating Wasm module")?;
+    let start = linker
+        .get_default("")?
+        .get0::<()>()
+        .context("finding '_start' export in Wasm module")?;
+
+    if let Err(trap) = start() {
+        // Exiting with zero status is reported as a trap too.
+        if trap.i32_exit_status() != Some(0) {
+            return Err(Error::Provider(
+                anyhow::Error::new(trap).context("running Wasm module"),
+            ));
+        }
+    }
+
+    let mut output = vec![];
+    stderr
+        .seek(SeekFrom::Start(0))
+        .and_then(|_| stderr.read_to_end(&mut output))
+        .context("reading stderr of Wasm module")?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
@@ -0,0 +1,108 @@
+use super::{clear_dir, ensure_module_exists, link_or_copy, spawn_blocking, Backend, Config};
+use crate::{package::Package, Error};
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tempfile::{tempdir, TempDir};
+
+/// Backend executing the Wasm module locally using an embedded Yagna WASI runtime.
+///
+/// The module is packaged and deployed exactly as it would be on a provider's node, which
+/// makes this backend a good choice for testing your app prior to launching it on the
+/// Golem Network.
+pub struct Local {
+    workspace: TempDir,
+    vol: Option<PathBuf>,
+}
+
+impl Local {
+    fn vol(&self) -> Result<&Path, Error> {
+        self.vol
+            .as_deref()
+            .ok_or_else(|| anyhow!("Yagna package was not deployed yet").into())
+    }
+}
+
+#[async_trait(?Send)]
+impl Backend for Local {
+    fn new(_config: &Config) -> Result<Self, Error> {
+        let workspace = tempdir().context("creating temp dir")?;
+        Ok(Self {
+            workspace,
+            vol: None,
+        })
+    }
+
+    async fn prepare(&mut self, module: &Path) -> Result<(), Error> {
+        ensure_module_exists(module)?;
+        let workspace = self.workspace.path().to_owned();
+        let module = module.to_owned();
+        let vol = spawn_blocking(move || {
+            let package_path = workspace.join("pkg.zip");
+            let mut package = Package::new();
+            package
+                .add_module_from_path(module)
+                .context("adding Wasm module from path")?;
+            package
+                .write(&package_path)
+                .context("saving Yagna zip package to file")?;
+
+            ya_runtime_wasi::deploy(&workspace, &package_path)
+                .context("deploying Yagna package")?;
+            ya_runtime_wasi::start(&workspace).context("executing Yagna start command")?;
+
+            let deployment = ya_runtime_wasi::DeployFile::load(&workspace)
+                .context("loading deployed Yagna package")?;
+            let vol = deployment
+                .vols()
+                .find(|vol| vol.path.starts_with("/workdir"))
+                .map(|vol| workspace.join(&vol.name))
+                .context("extracting workdir path from Yagna package")?;
+            Ok(vol)
+        })
+        .await?;
+        self.vol = Some(vol);
+        Ok(())
+    }
+
+    async fn upload(&mut self, name: &str, data: Vec<u8>) -> Result<(), Error> {
+        let input_path = self.vol()?.join(name);
+        fs::write(&input_path, data).context("writing serialized data to file")?;
+        Ok(())
+    }
+
+    async fn upload_file(&mut self, name: &str, path: &Path) -> Result<(), Error> {
+        link_or_copy(path, &self.vol()?.join(name))
+    }
+
+    async fn run(
+        &mut self,
+        entry_point: &str,
+        args: Vec<String>,
+        _outputs: &[&str],
+    ) -> Result<(), Error> {
+        let workspace = self.workspace.path().to_owned();
+        let entry_point = entry_point.to_owned();
+        spawn_blocking(move || {
+            ya_runtime_wasi::run(&workspace, &entry_point, args)
+                .context("executing Yagna run command")
+                .map_err(Error::Provider)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn download(&mut self, name: &str) -> Result<Vec<u8>, Error> {
+        let output_path = self.vol()?.join(name);
+        let output_data = fs::read(output_path).context("reading output data from file")?;
+        Ok(output_data)
+    }
+
+    /// Empties the workdir of the deployed package, which stays deployed.
+    async fn reset(&mut self) -> Result<(), Error> {
+        clear_dir(self.vol()?, &[])
+    }
+}
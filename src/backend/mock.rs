@@ -0,0 +1,154 @@
+use super::{Backend, Config, OUTPUT_TAG};
+use crate::Error;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+type Handler = Arc<dyn Fn(Vec<Vec<u8>>) -> Result<Vec<u8>, Error> + Send + Sync>;
+
+static HANDLERS: Mutex<BTreeMap<String, Handler>> = Mutex::new(BTreeMap::new());
+
+/// Backend which never leaves the process, and instead invokes the handler registered
+/// for the remote function with [`Mock::register`].
+///
+/// Handlers operate on serialized data: they receive the serialized inputs in order, and are
+/// expected to return the serialized output. For functions returning iterators, the output
+/// consists of the serialized items, each prefixed with its length as `u32` in little endian.
+/// Functions are registered by their names, e.g., `hello`, prefixed with the type of their
+/// `gfaas::remote_impl` block, e.g., `Point::norm`, or with their `gfaas::remote_mod`, e.g.,
+/// `shapes::area`. The functions of a group are registered as `<group>::<function>` instead,
+/// e.g., `geometry::area` for a function of the `geometry` group. The functions of the same
+/// name found in different modules share the handler.
+///
+/// ## Example
+///
+/// ```rust,ignore
+/// use gfaas::backend::Mock;
+///
+/// Mock::register("hello", |_inputs| Ok(br#""HEY THERE""#.to_vec()));
+/// Mock::register("shapes::area", |_inputs| Ok(b"1.0".to_vec()));
+/// gfaas::set_default_backend::<Mock>();
+/// ```
+///
+/// [`Mock::register`]: #method.register
+pub struct Mock {
+    inputs: Vec<Vec<u8>>,
+    output: Option<Vec<u8>>,
+}
+
+impl Mock {
+    /// Registers `handler` to be invoked in place of the remote function `name`, replacing
+    /// the previously registered handler, if any.
+    pub fn register<F>(name: &str, handler: F)
+    where
+        F: Fn(Vec<Vec<u8>>) -> Result<Vec<u8>, Error> + Send + Sync + 'static,
+    {
+        HANDLERS
+            .lock()
+            .unwrap()
+            .insert(name.to_owned(), Arc::new(handler));
+    }
+}
+
+#[async_trait(?Send)]
+impl Backend for Mock {
+    fn new(_config: &Config) -> Result<Self, Error> {
+        Ok(Self {
+            inputs: vec![],
+            output: None,
+        })
+    }
+
+    async fn prepare(&mut self, _module: &Path) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn upload(&mut self, _name: &str, data: Vec<u8>) -> Result<(), Error> {
+        self.inputs.push(data);
+        Ok(())
+    }
+
+    async fn run(
+        &mut self,
+        entry_point: &str,
+        args: Vec<String>,
+        _outputs: &[&str],
+    ) -> Result<(), Error> {
+        // The name of the module is the path of the function (or of the type, or the group, of
+        // the functions hosted by the module) prefixed with the crate, and suffixed with a hash,
+        // all joined with dashes, e.g., `app-shapes-area-1f2e3d4c` for `shapes::area` of crate
+        // `app`.
+        let parts: Vec<_> = entry_point.split('-').collect();
+        let path = match &parts[..] {
+            [_, path @ .., _] if !path.is_empty() => path.join("::"),
+            _ => entry_point.to_owned(),
+        };
+        // Unlike the paths to the inputs and the output, the name of the function within
+        // a module hosting several of them is not a path.
+        let name = match args.first() {
+            Some(function) if !function.starts_with('/') => format!("{}::{}", path, function),
+            _ => path,
+        };
+        let handler = HANDLERS
+            .lock()
+            .unwrap()
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| anyhow!("no mock handler registered for '{}'", name))?;
+        let inputs = std::mem::take(&mut self.inputs);
+        let mut output = vec![OUTPUT_TAG];
+        output.extend(handler(inputs)?);
+        self.output = Some(output);
+        Ok(())
+    }
+
+    async fn download(&mut self, _name: &str) -> Result<Vec<u8>, Error> {
+        self.output
+            .take()
+            .ok_or_else(|| anyhow!("mock handler was not run yet").into())
+    }
+
+    async fn reset(&mut self) -> Result<(), Error> {
+        self.inputs.clear();
+        self.output = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn run(entry_point: &str, args: &[&str]) -> Result<Vec<u8>, Error> {
+        let mut mock = Mock::new(&Config::default())?;
+        let args = args.iter().map(|arg| (*arg).to_owned()).collect();
+        mock.run(entry_point, args, &[]).await?;
+        mock.download("out").await
+    }
+
+    #[actix_rt::test]
+    async fn finds_handlers_by_path() {
+        Mock::register("mock_hello", |_| Ok(b"1".to_vec()));
+        Mock::register("mock_shapes::area", |_| Ok(b"2".to_vec()));
+        Mock::register("mock_group::double", |_| Ok(b"3".to_vec()));
+
+        let hello = run("app-mock_hello-1f2e3d4c", &["/in0", "/out"])
+            .await
+            .unwrap();
+        assert_eq!(hello, [OUTPUT_TAG, b'1']);
+        let area = run("app-mock_shapes-area-5a6b7c8d", &[]).await.unwrap();
+        assert_eq!(area, [OUTPUT_TAG, b'2']);
+        let double = run("app-mock_group-0a1b2c3d", &["double", "/in0"])
+            .await
+            .unwrap();
+        assert_eq!(double, [OUTPUT_TAG, b'3']);
+        let err = run("app-mock_shapes-volume-9e8d7c6b", &[])
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("'mock_shapes::volume'"));
+    }
+}
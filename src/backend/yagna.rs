@@ -0,0 +1,395 @@
+use super::{clear_dir, ensure_module_exists, upload_input, Backend, Config, Input};
+use crate::{package::Package, Error};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::future;
+use requestor::{Agreement, Command, Requestor};
+use serde_json::json;
+use std::{
+    cmp, fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tempfile::{tempdir, TempDir};
+use ya_agreement_utils::{constraints, ConstraintKey, Constraints};
+use ya_client::model::market::Demand;
+
+mod constraints;
+mod requestor;
+
+/// Name of the package within the workspace.
+const PACKAGE: &str = "pkg.zip";
+/// How long the activity kept for the subsequent invocations is negotiated for, unless
+/// the timeout is even longer.
+const DEPLOYMENT_LIFETIME: Duration = Duration::from_secs(30 * 60);
+
+/// Backend distributing the Wasm module on the Golem Network.
+///
+/// Requires a running Yagna daemon, and the `YAGNA_APPKEY` env variable set to the app key
+/// of the requestor. The budget is allocated before anything else, so a failure to allocate
+/// it is reported as [`Error::Budget`].
+///
+/// Proposals of the providers listed in [`Config::excluded_providers`] are turned down during
+/// the negotiation. The node id of every provider which failed to run its share of the work
+/// is reported by [`failed_providers`], so that it can be excluded on retry.
+///
+/// A single invocation keeps the agreement with the provider, along with the activity the
+/// package is deployed in, until the backend is released. This way, within a [`Session`],
+/// only the first invocation of a module goes through the market negotiation. The agreement
+/// is negotiated for 30 minutes, or for the timeout if longer, and renegotiated once there's
+/// not enough of it left for the next invocation. Batches of invocations negotiate agreements
+/// of their own, which end with the batch.
+///
+/// [`Error::Budget`]: ../enum.Error.html#variant.Budget
+/// [`Config::excluded_providers`]: struct.Config.html#structfield.excluded_providers
+/// [`failed_providers`]: trait.Backend.html#method.failed_providers
+/// [`Session`]: ../struct.Session.html
+pub struct Yagna {
+    config: Config,
+    workspace: TempDir,
+    commands: Vec<Command>,
+    failed_providers: Vec<String>,
+    requestor: Option<Requestor>,
+    deployment: Option<Deployment>,
+}
+
+/// Activity with the package deployed, kept for the subsequent invocations.
+struct Deployment {
+    agreement: Agreement,
+    activity_id: String,
+    /// When the agreement, and so the activity, expires.
+    expires: Instant,
+}
+
+#[async_trait(?Send)]
+impl Backend for Yagna {
+    fn new(config: &Config) -> Result<Self, Error> {
+        let workspace = tempdir().context("creating temp dir")?;
+        Ok(Self {
+            config: config.clone(),
+            workspace,
+            commands: vec![],
+            failed_providers: vec![],
+            requestor: None,
+            deployment: None,
+        })
+    }
+
+    async fn prepare(&mut self, module: &Path) -> Result<(), Error> {
+        ensure_module_exists(module)?;
+        let package_path = self.workspace.path().join(PACKAGE);
+        let mut package = Package::new();
+        package
+            .add_module_from_path(module)
+            .context("adding Wasm module from path")?;
+        package
+            .write(&package_path)
+            .context("saving Yagna zip package to file")?;
+        Ok(())
+    }
+
+    async fn upload(&mut self, name: &str, data: Vec<u8>) -> Result<(), Error> {
+        let input_path = self.workspace.path().join(name);
+        fs::write(&input_path, data).context("writing serialized data to file")?;
+        self.commands.push(Command::Upload {
+            from: input_path,
+            to: format!("/workdir/{}", name),
+        });
+        Ok(())
+    }
+
+    /// Uploads the file straight from `path`, without copying it into the workspace first.
+    async fn upload_file(&mut self, name: &str, path: &Path) -> Result<(), Error> {
+        self.commands.push(Command::Upload {
+            from: path.to_owned(),
+            to: format!("/workdir/{}", name),
+        });
+        Ok(())
+    }
+
+    async fn run(
+        &mut self,
+        entry_point: &str,
+        args: Vec<String>,
+        outputs: &[&str],
+    ) -> Result<(), Error> {
+        let mut run = vec![entry_point.to_owned()];
+        run.extend(args);
+        let mut commands = std::mem::take(&mut self.commands);
+        commands.push(Command::Run(run));
+        for name in outputs {
+            commands.push(self.download_command(name));
+        }
+        let mut requestor = self.take_requestor().await?;
+        let res = self.run_deployed(&mut requestor, commands).await;
+        self.requestor = Some(requestor);
+        res
+    }
+
+    async fn download(&mut self, name: &str) -> Result<Vec<u8>, Error> {
+        let output_path = self.workspace.path().join(name);
+        let output_data = fs::read(output_path).context("reading output data from file")?;
+        Ok(output_data)
+    }
+
+    /// Splits the `tasks` into at most `concurrency` exe-scripts, each executing its share of
+    /// the tasks one after another, and submits them all at once. This way, the market
+    /// negotiation happens only once, and every agreement is reused for multiple tasks.
+    /// Should fewer providers turn up, some of them run several of the exe-scripts.
+    async fn run_batch(
+        &mut self,
+        entry_point: &str,
+        function: Option<&str>,
+        tasks: Vec<Vec<Input>>,
+        concurrency: usize,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        let num_tasks = tasks.len();
+        let chunk_size = num_tasks.div_ceil(concurrency);
+        let mut lists = vec![];
+        let mut commands = vec![];
+        let output_names: Vec<_> = (0..num_tasks).map(|i| format!("out{}", i)).collect();
+        for (i, inputs) in tasks.into_iter().enumerate() {
+            let mut run = vec![entry_point.to_owned()];
+            run.extend(function.map(str::to_owned));
+            for (j, input) in inputs.into_iter().enumerate() {
+                let name = format!("in{}_{}", i, j);
+                upload_input(self, &name, input).await?;
+                run.push(format!("/workdir/{}", name));
+            }
+            run.push(format!("/workdir/{}", output_names[i]));
+            commands.append(&mut self.commands);
+            commands.push(Command::Run(run));
+            commands.push(self.download_command(&output_names[i]));
+
+            if (i + 1) % chunk_size == 0 || i + 1 == num_tasks {
+                lists.push(std::mem::take(&mut commands));
+            }
+        }
+        self.execute(lists).await?;
+
+        let mut outputs = Vec::with_capacity(num_tasks);
+        for name in &output_names {
+            outputs.push(self.download(name).await?);
+        }
+        Ok(outputs)
+    }
+
+    fn failed_providers(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.failed_providers)
+    }
+
+    /// Removes the inputs and outputs of the last invocation from the workspace, keeping the
+    /// package, as well as the activity it's deployed in.
+    async fn reset(&mut self) -> Result<(), Error> {
+        self.commands.clear();
+        clear_dir(self.workspace.path(), &[PACKAGE])
+    }
+
+    /// Destroys the activity kept for the subsequent invocations, if any, pays for the work
+    /// done, and releases the allocated budget.
+    async fn release(&mut self) {
+        if let Some(mut requestor) = self.requestor.take() {
+            self.undeploy(&mut requestor).await;
+            requestor.release().await;
+        }
+    }
+}
+
+impl Yagna {
+    fn download_command(&self, name: &str) -> Command {
+        Command::Download {
+            from: format!("/workdir/{}", name),
+            to: self.workspace.path().join(name),
+        }
+    }
+
+    /// Takes the requestor out of the backend, or connects a new one and allocates the budget
+    /// if there's none yet.
+    async fn take_requestor(&mut self) -> Result<Requestor, Error> {
+        if let Some(requestor) = self.requestor.take() {
+            return Ok(requestor);
+        }
+        let mut requestor = Requestor::new()?;
+        requestor.allocate(self.config.budget).await?;
+        Ok(requestor)
+    }
+
+    /// Runs the `commands` within the activity kept from the previous invocations, or deploys
+    /// the package within a new one first if there's none, or it would expire before
+    /// the timeout.
+    async fn run_deployed(
+        &mut self,
+        requestor: &mut Requestor,
+        commands: Vec<Command>,
+    ) -> Result<(), Error> {
+        let now = Instant::now();
+        let deadline = now + self.config.timeout;
+        if matches!(&self.deployment, Some(deployment) if deployment.expires < deadline) {
+            self.undeploy(requestor).await;
+        }
+        let deployment = match self.deployment.take() {
+            Some(deployment) => deployment,
+            None => {
+                let lifetime = cmp::max(self.config.timeout, DEPLOYMENT_LIFETIME);
+                let expiration = expiration(lifetime)?;
+                let demand = self.demand(requestor, expiration).await?;
+                let agreement = requestor
+                    .negotiate(
+                        &demand,
+                        1,
+                        &self.config.excluded_providers,
+                        expiration,
+                        deadline,
+                    )
+                    .await?
+                    .remove(0);
+                match requestor.create_activity(&agreement, deadline).await {
+                    Ok(activity_id) => Deployment {
+                        agreement,
+                        activity_id,
+                        expires: now + lifetime,
+                    },
+                    Err(err) => {
+                        requestor.terminate(&agreement).await;
+                        requestor.pay(std::slice::from_ref(&agreement)).await;
+                        if err.is_retryable() {
+                            self.failed_providers.push(agreement.provider_id);
+                        }
+                        return Err(err);
+                    }
+                }
+            }
+        };
+        let res = requestor
+            .exec(&deployment.activity_id, commands, deadline)
+            .await;
+        if matches!(&res, Err(err) if err.is_retryable()) {
+            self.failed_providers
+                .push(deployment.agreement.provider_id.clone());
+        }
+        self.deployment = Some(deployment);
+        res
+    }
+
+    /// Destroys the activity kept for the subsequent invocations, if any, and pays for it.
+    async fn undeploy(&mut self, requestor: &mut Requestor) {
+        if let Some(deployment) = self.deployment.take() {
+            requestor.destroy_activity(&deployment.activity_id).await;
+            requestor.terminate(&deployment.agreement).await;
+            requestor.pay(&[deployment.agreement]).await;
+        }
+    }
+
+    /// Negotiates agreements for the exe-scripts made of the `lists` of commands, and runs
+    /// them until they all complete or the timeout elapses.
+    ///
+    /// This is what yarapi's `Requestor::with_tasks` used to do for us, except that it knows
+    /// which provider ran which exe-script, so that the failed ones can be excluded on retry,
+    /// and that it pays out of the budget allocated upfront, which tells a budget failure from
+    /// the others. Neither was possible with yarapi, which is why it was replaced.
+    async fn execute(&mut self, lists: Vec<Vec<Command>>) -> Result<(), Error> {
+        let deadline = Instant::now() + self.config.timeout;
+        let mut requestor = self.take_requestor().await?;
+        let res = self.execute_with(&mut requestor, lists, deadline).await;
+        self.requestor = Some(requestor);
+        res
+    }
+
+    async fn execute_with(
+        &mut self,
+        requestor: &mut Requestor,
+        lists: Vec<Vec<Command>>,
+        deadline: Instant,
+    ) -> Result<(), Error> {
+        let expiration = expiration(self.config.timeout)?;
+        let demand = self.demand(requestor, expiration).await?;
+        let agreements = requestor
+            .negotiate(
+                &demand,
+                lists.len(),
+                &self.config.excluded_providers,
+                expiration,
+                deadline,
+            )
+            .await?;
+
+        // Every agreement runs its share of the exe-scripts one after another, within
+        // a single activity.
+        let mut shares = vec![vec![]; agreements.len()];
+        for (i, commands) in lists.into_iter().enumerate() {
+            shares[i % agreements.len()].push(commands);
+        }
+        let runs = agreements.iter().zip(shares).map(|(agreement, lists)| {
+            let requestor = &*requestor;
+            async move {
+                let activity_id = requestor.create_activity(agreement, deadline).await?;
+                let mut res = Ok(());
+                for commands in lists {
+                    res = requestor.exec(&activity_id, commands, deadline).await;
+                    if res.is_err() {
+                        break;
+                    }
+                }
+                requestor.destroy_activity(&activity_id).await;
+                res
+            }
+        });
+        let results = future::join_all(runs).await;
+
+        for agreement in &agreements {
+            requestor.terminate(agreement).await;
+        }
+        requestor.pay(&agreements).await;
+        for (agreement, res) in agreements.iter().zip(&results) {
+            if matches!(res, Err(err) if err.is_retryable()) {
+                self.failed_providers.push(agreement.provider_id.clone());
+            }
+        }
+        results.into_iter().collect()
+    }
+
+    /// Publishes the package, and describes the providers which may run it until `expiration`.
+    async fn demand(
+        &self,
+        requestor: &Requestor,
+        expiration: DateTime<Utc>,
+    ) -> Result<Demand, Error> {
+        let config = &self.config;
+        let mut constraints = constraints![
+            "golem.runtime.name" == "wasmtime",
+            "golem.runtime.version" == "0.0.0",
+            "golem.com.pricing.model" == "linear",
+            "golem.node.debug.subnet" == config.subnet.as_str(),
+            "golem.inf.mem.gib" > config.min_mem_gib,
+            "golem.inf.storage.gib" > config.min_storage_gib,
+        ];
+        if let Some(threads) = config.min_cpu_threads {
+            // Yagna's constraints lack `>=`, which is fine as the number of threads is whole.
+            constraints = constraints.and(constraints!["golem.inf.cpu.threads" > threads - 1]);
+        }
+        for expr in &config.constraints {
+            let parsed = constraints::parse(expr)
+                .with_context(|| format!("parsing constraints '{}'", expr))?;
+            constraints = constraints.and(parsed);
+        }
+
+        let task_package = requestor
+            .publish(&self.workspace.path().join(PACKAGE))
+            .await?;
+        Ok(Demand::new(
+            json!({
+                "golem.node.id.name": "gfaas",
+                "golem.node.debug.subnet": config.subnet,
+                "golem.srv.comp.task_package": task_package,
+                "golem.srv.comp.expiration": expiration.timestamp_millis(),
+            }),
+            constraints.to_string(),
+        ))
+    }
+}
+
+/// Returns the point in time `lifetime` from now.
+fn expiration(lifetime: Duration) -> Result<DateTime<Utc>, Error> {
+    Ok(Utc::now() + chrono::Duration::from_std(lifetime).context("invalid timeout")?)
+}
@@ -0,0 +1,69 @@
+//! Constraints given in the syntax of Golem's demands, as parsed by gfaas-constraints.
+use anyhow::Result;
+use gfaas_constraints::{Comparison, Expr, Operator};
+use ya_agreement_utils::{ClauseOperator, ConstraintExpr, ConstraintKey, Constraints};
+
+/// Parses `input` consisting of one or more parenthesized expressions, all of which have to be
+/// satisfied, e.g., `(golem.inf.cpu.threads>3)(|(golem.node.id.name=a)(golem.node.id.name=b))`.
+pub(super) fn parse(input: &str) -> Result<Constraints> {
+    let exprs = gfaas_constraints::parse(input)?;
+    Ok(Constraints::new_clause(
+        ClauseOperator::And,
+        exprs.into_iter().map(convert).collect(),
+    ))
+}
+
+fn convert(expr: Expr) -> ConstraintExpr {
+    match expr {
+        Expr::Clause(operator, exprs) => {
+            let operator = match operator {
+                Operator::And => ClauseOperator::And,
+                Operator::Or => ClauseOperator::Or,
+            };
+            Constraints::new_clause(operator, exprs.into_iter().map(convert).collect()).into()
+        }
+        Expr::Property(name, None) => ConstraintKey::new(name).into(),
+        Expr::Property(name, Some((comparison, value))) => {
+            let key = ConstraintKey::new(name);
+            let value = ConstraintKey::new(value);
+            match comparison {
+                Comparison::Equal => key.equal_to(value),
+                Comparison::NotEqual => key.not_equal_to(value),
+                Comparison::Less => key.less_than(value),
+                Comparison::Greater => key.greater_than(value),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_expressions() {
+        let constraints = parse(
+            " (golem.inf.cpu.threads > 3)(|(golem.node.id.name=a b)(golem.node.id.name<>c))\
+             (golem.runtime.name)",
+        )
+        .unwrap();
+        let name = || ConstraintKey::new("golem.node.id.name");
+        let expected = Constraints::new_clause(
+            ClauseOperator::And,
+            vec![
+                ConstraintKey::new("golem.inf.cpu.threads").greater_than(ConstraintKey::new("3")),
+                Constraints::new_clause(
+                    ClauseOperator::Or,
+                    vec![
+                        name().equal_to(ConstraintKey::new("a b")),
+                        name().not_equal_to(ConstraintKey::new("c")),
+                    ],
+                )
+                .into(),
+                ConstraintKey::new("golem.runtime.name").into(),
+            ],
+        );
+        assert_eq!(constraints.to_string(), expected.to_string());
+        assert!(parse("(a=b").is_err());
+    }
+}
@@ -0,0 +1,747 @@
+//! Requestor side of the Golem Network, talking to the Yagna daemon through its REST API.
+//!
+//! It stands in for yarapi's requestor, which neither tells which provider signed which
+//! agreement, nor keeps an agreement beyond a single run, nor reports which of its steps
+//! failed, e.g., the allocation of the budget.
+
+use crate::{Backoff, Error};
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sha3::{Digest, Sha3_512};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tokio::time;
+use ya_client::{
+    activity::ActivityRequestorApi,
+    market::MarketRequestorApi,
+    model::{
+        activity::{CommandResult, ExeScriptRequest},
+        market::{proposal::State, AgreementProposal, Demand, Proposal, RequestorEvent},
+        payment::{Acceptance, Allocation, EventType, NewAllocation},
+    },
+    payment::PaymentRequestorApi,
+    web::WebClient,
+};
+
+/// How long a single poll of the daemon for market events, exe-script results or invoices
+/// may take.
+const POLL_TIMEOUT: Duration = Duration::from_secs(3);
+/// How long to wait for the invoices of the providers once the computation is done.
+const INVOICE_TIMEOUT: Duration = Duration::from_secs(60);
+/// How many times in a row collecting the market events may fail before giving up.
+const COLLECT_RETRIES: u32 = 3;
+/// Spacing out the attempts to collect the market events after a failure.
+const COLLECT_BACKOFF: Backoff = Backoff::Exponential {
+    initial: Duration::from_millis(250),
+    max: POLL_TIMEOUT,
+};
+
+/// Command of an exe-script.
+#[derive(Clone, Debug)]
+pub(super) enum Command {
+    /// Uploads the local file `from` to the path `to` in the sandbox.
+    Upload { from: PathBuf, to: String },
+    /// Runs the entry point, which is the first element, with the rest as arguments.
+    Run(Vec<String>),
+    /// Downloads the file `from` in the sandbox to the local path `to`.
+    Download { from: String, to: PathBuf },
+}
+
+/// Agreement with a provider.
+#[derive(Clone, Debug)]
+pub(super) struct Agreement {
+    pub id: String,
+    /// Node id of the provider.
+    pub provider_id: String,
+}
+
+/// Market API of the Yagna daemon, as far as the negotiation goes, so that it can be mocked.
+#[async_trait(?Send)]
+pub(super) trait Market {
+    /// Publishes the `demand`, and returns the id of the subscription.
+    async fn subscribe(&self, demand: &Demand) -> anyhow::Result<String>;
+    async fn unsubscribe(&self, subscription_id: &str) -> anyhow::Result<()>;
+    /// Waits up to `timeout` for at most `max_events` events of the subscription.
+    async fn collect(
+        &self,
+        subscription_id: &str,
+        timeout: Duration,
+        max_events: usize,
+    ) -> anyhow::Result<Vec<RequestorEvent>>;
+    async fn counter_proposal(
+        &self,
+        proposal: &Proposal,
+        subscription_id: &str,
+    ) -> anyhow::Result<()>;
+    /// Creates the agreement from the `proposal`, and returns its id.
+    async fn create_agreement(&self, proposal: &AgreementProposal) -> anyhow::Result<String>;
+    async fn confirm_agreement(&self, agreement_id: &str) -> anyhow::Result<()>;
+    /// Waits up to `timeout` for the provider to approve the agreement, and returns its answer.
+    async fn wait_for_approval(
+        &self,
+        agreement_id: &str,
+        timeout: Duration,
+    ) -> anyhow::Result<String>;
+    /// Returns the node id of the provider of the agreement.
+    async fn provider_id(&self, agreement_id: &str) -> anyhow::Result<String>;
+    async fn terminate_agreement(&self, agreement_id: &str) -> anyhow::Result<()>;
+}
+
+#[async_trait(?Send)]
+impl Market for MarketRequestorApi {
+    async fn subscribe(&self, demand: &Demand) -> anyhow::Result<String> {
+        Ok(self.subscribe(demand).await?)
+    }
+
+    async fn unsubscribe(&self, subscription_id: &str) -> anyhow::Result<()> {
+        self.unsubscribe(subscription_id).await?;
+        Ok(())
+    }
+
+    async fn collect(
+        &self,
+        subscription_id: &str,
+        timeout: Duration,
+        max_events: usize,
+    ) -> anyhow::Result<Vec<RequestorEvent>> {
+        let max_events = max_events.min(i32::MAX as usize) as i32;
+        Ok(self
+            .collect(
+                subscription_id,
+                Some(timeout.as_secs_f32()),
+                Some(max_events),
+            )
+            .await?)
+    }
+
+    async fn counter_proposal(
+        &self,
+        proposal: &Proposal,
+        subscription_id: &str,
+    ) -> anyhow::Result<()> {
+        self.counter_proposal(proposal, subscription_id).await?;
+        Ok(())
+    }
+
+    async fn create_agreement(&self, proposal: &AgreementProposal) -> anyhow::Result<String> {
+        Ok(self.create_agreement(proposal).await?)
+    }
+
+    async fn confirm_agreement(&self, agreement_id: &str) -> anyhow::Result<()> {
+        self.confirm_agreement(agreement_id).await?;
+        Ok(())
+    }
+
+    async fn wait_for_approval(
+        &self,
+        agreement_id: &str,
+        timeout: Duration,
+    ) -> anyhow::Result<String> {
+        Ok(self
+            .wait_for_approval(agreement_id, Some(timeout.as_secs_f32()))
+            .await?)
+    }
+
+    async fn provider_id(&self, agreement_id: &str) -> anyhow::Result<String> {
+        let agreement = self.get_agreement(agreement_id).await?;
+        Ok(agreement.provider_id()?.to_owned())
+    }
+
+    async fn terminate_agreement(&self, agreement_id: &str) -> anyhow::Result<()> {
+        self.terminate_agreement(agreement_id).await?;
+        Ok(())
+    }
+}
+
+/// Requestor negotiating agreements, running activities, and paying for them out of
+/// the allocated budget.
+pub(super) struct Requestor {
+    market: Box<dyn Market>,
+    activity: ActivityRequestorApi,
+    payment: PaymentRequestorApi,
+    allocation: Option<Allocation>,
+    last_invoice_event: DateTime<Utc>,
+}
+
+impl Requestor {
+    /// Connects to the Yagna daemon with the app key from the `YAGNA_APPKEY` env variable.
+    pub fn new() -> Result<Self, Error> {
+        let app_key = env::var("YAGNA_APPKEY").context("reading YAGNA_APPKEY env variable")?;
+        let client = WebClient::builder().auth_token(&app_key).build();
+        let market: MarketRequestorApi = client.interface().context("connecting to market API")?;
+        Self::with_market(&client, Box::new(market))
+    }
+
+    /// Connects to the Yagna daemon with the `client`, negotiating through the `market`.
+    fn with_market(client: &WebClient, market: Box<dyn Market>) -> Result<Self, Error> {
+        Ok(Self {
+            market,
+            activity: client.interface().context("connecting to activity API")?,
+            payment: client.interface().context("connecting to payment API")?,
+            allocation: None,
+            last_invoice_event: Utc::now(),
+        })
+    }
+
+    /// Allocates the `budget` for paying the providers, unless it's been allocated already.
+    ///
+    /// Fails with [`Error::Budget`] if the daemon refuses the allocation, e.g., because
+    /// the account has insufficient funds.
+    ///
+    /// [`Error::Budget`]: ../../enum.Error.html#variant.Budget
+    pub async fn allocate(&mut self, budget: u64) -> Result<(), Error> {
+        if self.allocation.is_some() {
+            return Ok(());
+        }
+        let res = self
+            .payment
+            .create_allocation(&NewAllocation {
+                address: None,
+                payment_platform: None,
+                total_amount: BigDecimal::from(budget),
+                timeout: None,
+                make_deposit: false,
+            })
+            .await;
+        match res {
+            Ok(allocation) => {
+                self.allocation = Some(allocation);
+                Ok(())
+            }
+            // The daemon understood the request, but turned it down.
+            Err(err @ ya_client::Error::HttpStatusCode { .. }) => {
+                Err(Error::Budget(anyhow!(err).context("allocating budget")))
+            }
+            Err(err) => Err(anyhow!(err).context("allocating budget").into()),
+        }
+    }
+
+    /// Releases the budget allocated with [`allocate`], if any.
+    ///
+    /// [`allocate`]: #method.allocate
+    pub async fn release(&mut self) {
+        if let Some(allocation) = self.allocation.take() {
+            let res = self
+                .payment
+                .release_allocation(&allocation.allocation_id)
+                .await;
+            if let Err(err) = res {
+                log::warn!("releasing allocation: {}", err);
+            }
+        }
+    }
+
+    /// Publishes the package at `path` for the providers to download, and returns its URL
+    /// together with its hash, as expected by the demand.
+    pub async fn publish(&self, path: &Path) -> Result<String, Error> {
+        let contents = fs::read(path).context("reading Yagna package")?;
+        let digest = Sha3_512::digest(&contents);
+        let url = gftp::publish(path)
+            .await
+            .context("publishing Yagna package")?;
+        Ok(format!("hash:sha3:{:x}:{}", digest, url))
+    }
+
+    /// Negotiates agreements valid until `expiration` with up to `count` distinct providers,
+    /// other than those with the `excluded` node ids. Gives up once it has at least one
+    /// agreement and the market has no new proposals, or when the `deadline` passes, in which
+    /// case it fails with [`Error::MarketTimeout`] unless it has at least one agreement.
+    ///
+    /// Failing to collect the market events is retried a few times with a backoff, before
+    /// the negotiation fails with the last error. The proposals which fail to turn into
+    /// agreements are merely logged, since the other providers may still come along.
+    ///
+    /// [`Error::MarketTimeout`]: ../../enum.Error.html#variant.MarketTimeout
+    pub async fn negotiate(
+        &self,
+        demand: &Demand,
+        count: usize,
+        excluded: &[String],
+        expiration: DateTime<Utc>,
+        deadline: Instant,
+    ) -> Result<Vec<Agreement>, Error> {
+        let subscription_id = self
+            .market
+            .subscribe(demand)
+            .await
+            .context("subscribing to market")?;
+        let res = self
+            .collect_agreements(
+                &subscription_id,
+                demand,
+                count,
+                excluded,
+                expiration,
+                deadline,
+            )
+            .await;
+        if let Err(err) = self.market.unsubscribe(&subscription_id).await {
+            log::warn!("unsubscribing from market: {:#}", err);
+        }
+        match res {
+            Ok(agreements) if agreements.is_empty() => Err(Error::MarketTimeout),
+            res => res,
+        }
+    }
+
+    async fn collect_agreements(
+        &self,
+        subscription_id: &str,
+        demand: &Demand,
+        count: usize,
+        excluded: &[String],
+        expiration: DateTime<Utc>,
+        deadline: Instant,
+    ) -> Result<Vec<Agreement>, Error> {
+        let mut agreements: Vec<Agreement> = vec![];
+        let mut failures = 0;
+        while agreements.len() < count && Instant::now() < deadline {
+            let events = match self
+                .market
+                .collect(subscription_id, POLL_TIMEOUT, count * 2)
+                .await
+            {
+                Ok(events) => {
+                    failures = 0;
+                    events
+                }
+                Err(err) if failures < COLLECT_RETRIES => {
+                    log::warn!("collecting market events, retrying: {:#}", err);
+                    time::delay_for(COLLECT_BACKOFF.delay(failures)).await;
+                    failures += 1;
+                    continue;
+                }
+                // The agreements made so far are still good to use, and have to be paid for.
+                Err(err) if !agreements.is_empty() => {
+                    log::warn!("collecting market events: {:#}", err);
+                    break;
+                }
+                Err(err) => return Err(err.context("collecting market events").into()),
+            };
+            if events.is_empty() && !agreements.is_empty() {
+                break;
+            }
+            for event in events {
+                let proposal = match event {
+                    RequestorEvent::ProposalEvent { proposal, .. } => proposal,
+                    _ => continue,
+                };
+                let issuer_id = proposal.issuer_id.clone().unwrap_or_default();
+                if agreements.len() == count
+                    || excluded.contains(&issuer_id)
+                    || agreements.iter().any(|a| a.provider_id == issuer_id)
+                {
+                    continue;
+                }
+                match proposal.state.as_ref().unwrap_or(&State::Initial) {
+                    State::Initial => {
+                        let res = match proposal.counter_demand(demand.clone()) {
+                            Ok(counter) => {
+                                self.market
+                                    .counter_proposal(&counter, subscription_id)
+                                    .await
+                            }
+                            Err(err) => Err(anyhow!(err)),
+                        };
+                        if let Err(err) = res {
+                            log::warn!("counter-proposing to provider '{}': {:#}", issuer_id, err);
+                        }
+                    }
+                    State::Draft => match self.agree(&proposal, expiration).await {
+                        Ok(agreement) => agreements.push(agreement),
+                        Err(err) => log::warn!("agreeing with provider '{}': {:#}", issuer_id, err),
+                    },
+                    _ => {}
+                }
+            }
+        }
+        Ok(agreements)
+    }
+
+    /// Turns the draft `proposal` into an agreement valid until `valid_to`.
+    async fn agree(
+        &self,
+        proposal: &Proposal,
+        valid_to: DateTime<Utc>,
+    ) -> anyhow::Result<Agreement> {
+        let proposal_id = proposal.proposal_id()?.clone();
+        let agreement_id = self
+            .market
+            .create_agreement(&AgreementProposal::new(proposal_id, valid_to))
+            .await?;
+        self.market.confirm_agreement(&agreement_id).await?;
+        let response = self
+            .market
+            .wait_for_approval(&agreement_id, Duration::from_secs(10))
+            .await?;
+        if !response.trim().eq_ignore_ascii_case("approved") {
+            return Err(anyhow!("agreement {} was not approved", agreement_id));
+        }
+        let provider_id = self.market.provider_id(&agreement_id).await?;
+        Ok(Agreement {
+            id: agreement_id,
+            provider_id,
+        })
+    }
+
+    /// Terminates the `agreement`, which makes the provider send its invoice.
+    pub async fn terminate(&self, agreement: &Agreement) {
+        if let Err(err) = self.market.terminate_agreement(&agreement.id).await {
+            log::warn!("terminating agreement {}: {:#}", agreement.id, err);
+        }
+    }
+
+    /// Creates an activity within the `agreement`, and deploys and starts the package.
+    pub async fn create_activity(
+        &self,
+        agreement: &Agreement,
+        deadline: Instant,
+    ) -> Result<String, Error> {
+        let activity_id = self
+            .activity
+            .control()
+            .create_activity(&agreement.id)
+            .await
+            .context("creating activity")
+            .map_err(Error::Provider)?;
+        let script = vec![json!({ "deploy": {} }), json!({ "start": { "args": [] } })];
+        if let Err(err) = self.exec_script(&activity_id, script, deadline).await {
+            self.destroy_activity(&activity_id).await;
+            return Err(err);
+        }
+        Ok(activity_id)
+    }
+
+    /// Destroys the activity.
+    pub async fn destroy_activity(&self, activity_id: &str) {
+        if let Err(err) = self.activity.control().destroy_activity(activity_id).await {
+            log::warn!("destroying activity {}: {}", activity_id, err);
+        }
+    }
+
+    /// Executes the `commands` within the activity, and waits until they all complete, or
+    /// the `deadline` passes, in which case it fails with [`Error::ExecutionTimeout`].
+    ///
+    /// [`Error::ExecutionTimeout`]: ../../enum.Error.html#variant.ExecutionTimeout
+    pub async fn exec(
+        &self,
+        activity_id: &str,
+        commands: Vec<Command>,
+        deadline: Instant,
+    ) -> Result<(), Error> {
+        let mut script = Vec::with_capacity(commands.len());
+        for command in commands {
+            script.push(match command {
+                Command::Upload { from, to } => {
+                    let url = gftp::publish(&from)
+                        .await
+                        .with_context(|| format!("publishing '{}'", from.display()))?;
+                    json!({ "transfer": { "from": url, "to": format!("container:{}", to) } })
+                }
+                Command::Run(mut run) => {
+                    let entry_point = run.remove(0);
+                    json!({ "run": { "entry_point": entry_point, "args": run } })
+                }
+                Command::Download { from, to } => {
+                    let url = gftp::open_for_upload(&to)
+                        .await
+                        .with_context(|| format!("opening '{}' for download", to.display()))?;
+                    json!({ "transfer": { "from": format!("container:{}", from), "to": url } })
+                }
+            });
+        }
+        self.exec_script(activity_id, script, deadline).await
+    }
+
+    async fn exec_script(
+        &self,
+        activity_id: &str,
+        script: Vec<serde_json::Value>,
+        deadline: Instant,
+    ) -> Result<(), Error> {
+        let control = self.activity.control();
+        let script = serde_json::to_string(&script).context("serializing exe-script")?;
+        let batch_id = control
+            .exec(ExeScriptRequest::new(script), activity_id)
+            .await
+            .context("executing exe-script")
+            .map_err(Error::Provider)?;
+        loop {
+            if Instant::now() >= deadline {
+                return Err(Error::ExecutionTimeout);
+            }
+            let results = control
+                .get_exec_batch_results(
+                    activity_id,
+                    &batch_id,
+                    Some(POLL_TIMEOUT.as_secs_f32()),
+                    None,
+                )
+                .await
+                .context("fetching exe-script results")
+                .map_err(Error::Provider)?;
+            if let Some(failed) = results.iter().find(|r| r.result == CommandResult::Error) {
+                return Err(Error::Provider(anyhow!(
+                    "command {} of exe-script failed: {}",
+                    failed.index,
+                    failed.message.as_deref().unwrap_or_default()
+                )));
+            }
+            if results.last().is_some_and(|r| r.is_batch_finished) {
+                return Ok(());
+            }
+            let state = self
+                .activity
+                .state()
+                .get_state(activity_id)
+                .await
+                .context("fetching activity state")
+                .map_err(Error::Provider)?;
+            if !state.alive() {
+                return Err(Error::Provider(anyhow!("activity is no longer alive")));
+            }
+        }
+    }
+
+    /// Accepts the invoices for the terminated `agreements` as they arrive, giving up after
+    /// a while on the providers which don't send them.
+    pub async fn pay(&mut self, agreements: &[Agreement]) {
+        let allocation_id = match &self.allocation {
+            Some(allocation) => allocation.allocation_id.clone(),
+            None => return,
+        };
+        let mut unpaid: Vec<_> = agreements.iter().map(|a| a.id.as_str()).collect();
+        let deadline = Instant::now() + INVOICE_TIMEOUT;
+        while !unpaid.is_empty() && Instant::now() < deadline {
+            let events = match self
+                .payment
+                .get_invoice_events(Some(&self.last_invoice_event), Some(POLL_TIMEOUT))
+                .await
+            {
+                Ok(events) => events,
+                Err(err) => {
+                    log::warn!("fetching invoice events: {}", err);
+                    time::delay_for(POLL_TIMEOUT).await;
+                    continue;
+                }
+            };
+            for event in events {
+                self.last_invoice_event = event.timestamp;
+                if event.event_type != EventType::Received {
+                    continue;
+                }
+                let invoice = match self.payment.get_invoice(&event.invoice_id).await {
+                    Ok(invoice) => invoice,
+                    Err(_) => continue,
+                };
+                if let Some(i) = unpaid.iter().position(|id| *id == invoice.agreement_id) {
+                    let acceptance = Acceptance {
+                        total_amount_accepted: invoice.amount,
+                        allocation_id: allocation_id.clone(),
+                    };
+                    if self
+                        .payment
+                        .accept_invoice(&invoice.invoice_id, &acceptance)
+                        .await
+                        .is_ok()
+                    {
+                        unpaid.remove(i);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        cell::{Cell, RefCell},
+        collections::VecDeque,
+        rc::Rc,
+    };
+
+    /// Market replaying batches of `events`, and then either nothing, or failures if `down`.
+    /// It approves the agreements with all but the `rejecting` providers.
+    #[derive(Default)]
+    struct MockMarket {
+        events: RefCell<VecDeque<Vec<RequestorEvent>>>,
+        down: bool,
+        rejecting: Vec<String>,
+        collected: Cell<u32>,
+        countered: RefCell<Vec<String>>,
+        unsubscribed: Cell<bool>,
+    }
+
+    #[async_trait(?Send)]
+    impl Market for Rc<MockMarket> {
+        async fn subscribe(&self, _demand: &Demand) -> anyhow::Result<String> {
+            Ok("subscription".to_owned())
+        }
+
+        async fn unsubscribe(&self, _subscription_id: &str) -> anyhow::Result<()> {
+            self.unsubscribed.set(true);
+            Ok(())
+        }
+
+        async fn collect(
+            &self,
+            _subscription_id: &str,
+            _timeout: Duration,
+            _max_events: usize,
+        ) -> anyhow::Result<Vec<RequestorEvent>> {
+            self.collected.set(self.collected.get() + 1);
+            match self.events.borrow_mut().pop_front() {
+                Some(events) => Ok(events),
+                None if self.down => Err(anyhow!("daemon is down")),
+                None => Ok(vec![]),
+            }
+        }
+
+        async fn counter_proposal(
+            &self,
+            proposal: &Proposal,
+            _subscription_id: &str,
+        ) -> anyhow::Result<()> {
+            let prev_id = proposal.prev_proposal_id.clone().unwrap_or_default();
+            self.countered.borrow_mut().push(prev_id);
+            Ok(())
+        }
+
+        async fn create_agreement(&self, proposal: &AgreementProposal) -> anyhow::Result<String> {
+            Ok(proposal.proposal_id.clone())
+        }
+
+        async fn confirm_agreement(&self, _agreement_id: &str) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn wait_for_approval(
+            &self,
+            agreement_id: &str,
+            _timeout: Duration,
+        ) -> anyhow::Result<String> {
+            let provider_id = self.provider_id(agreement_id).await?;
+            Ok(if self.rejecting.contains(&provider_id) {
+                "Rejected".to_owned()
+            } else {
+                "Approved".to_owned()
+            })
+        }
+
+        async fn provider_id(&self, agreement_id: &str) -> anyhow::Result<String> {
+            Ok(agreement_id.trim_end_matches("-draft").to_owned())
+        }
+
+        async fn terminate_agreement(&self, _agreement_id: &str) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Proposal of the `provider`, whose id is the provider's followed by `-draft` for drafts.
+    fn proposal(provider: &str, draft: bool) -> RequestorEvent {
+        let mut proposal = Proposal::new(json!({}), String::new());
+        proposal.issuer_id = Some(provider.to_owned());
+        if draft {
+            proposal.proposal_id = Some(format!("{}-draft", provider));
+            proposal.state = Some(State::Draft);
+        } else {
+            proposal.proposal_id = Some(provider.to_owned());
+            proposal.state = Some(State::Initial);
+        }
+        RequestorEvent::ProposalEvent {
+            event_date: Utc::now(),
+            proposal,
+        }
+    }
+
+    async fn negotiate(
+        market: &Rc<MockMarket>,
+        count: usize,
+        timeout: Duration,
+    ) -> Result<Vec<Agreement>, Error> {
+        let client = WebClient::builder().build();
+        let requestor = Requestor::with_market(&client, Box::new(market.clone()))?;
+        let demand = Demand::new(json!({}), String::new());
+        let excluded = ["excluded".to_owned()];
+        let expiration = Utc::now() + chrono::Duration::minutes(1);
+        let deadline = Instant::now() + timeout;
+        requestor
+            .negotiate(&demand, count, &excluded, expiration, deadline)
+            .await
+    }
+
+    #[actix_rt::test]
+    async fn agrees_with_distinct_providers() {
+        let market = Rc::new(MockMarket {
+            events: RefCell::new(VecDeque::from(vec![
+                vec![
+                    proposal("a", false),
+                    proposal("b", false),
+                    proposal("excluded", false),
+                ],
+                vec![
+                    proposal("a", true),
+                    proposal("a", true),
+                    proposal("b", true),
+                    proposal("excluded", true),
+                ],
+            ])),
+            rejecting: vec!["b".to_owned()],
+            ..MockMarket::default()
+        });
+        let agreements = negotiate(&market, 3, Duration::from_secs(10))
+            .await
+            .unwrap();
+        let providers: Vec<_> = agreements.iter().map(|a| a.provider_id.as_str()).collect();
+        assert_eq!(providers, ["a"]);
+        assert_eq!(*market.countered.borrow(), ["a", "b"]);
+        assert!(market.unsubscribed.get());
+    }
+
+    #[actix_rt::test]
+    async fn fails_when_market_is_down() {
+        let market = Rc::new(MockMarket {
+            down: true,
+            ..MockMarket::default()
+        });
+        let err = negotiate(&market, 1, Duration::from_secs(30))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Other(_)), "{:?}", err);
+        assert_eq!(err.to_string(), "collecting market events");
+        let source = std::error::Error::source(&err).unwrap();
+        assert_eq!(source.to_string(), "daemon is down");
+        assert_eq!(market.collected.get(), COLLECT_RETRIES + 1);
+        assert!(market.unsubscribed.get());
+    }
+
+    #[actix_rt::test]
+    async fn keeps_agreements_when_market_goes_down() {
+        let market = Rc::new(MockMarket {
+            events: RefCell::new(VecDeque::from(vec![vec![proposal("a", true)]])),
+            down: true,
+            ..MockMarket::default()
+        });
+        let agreements = negotiate(&market, 2, Duration::from_secs(30))
+            .await
+            .unwrap();
+        assert_eq!(agreements.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn times_out_without_proposals() {
+        let market = Rc::new(MockMarket::default());
+        let err = negotiate(&market, 1, Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::MarketTimeout), "{:?}", err);
+    }
+}
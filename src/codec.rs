@@ -0,0 +1,150 @@
+//! Serialization formats of the inputs and outputs of remote functions.
+//!
+//! The format is selected with the `codec` argument to `gfaas::remote_fn` attribute, and
+//! defaults to JSON. Other than JSON, each format is gated behind the cargo feature of the
+//! same name, i.e., `bincode`, `cbor` and `msgpack`.
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+
+/// Serialization format of the inputs and outputs of remote functions.
+///
+/// The Wasm module uses the matching crate to (de)serialize its side of the data, so the
+/// format can't be freely swapped for a custom one.
+pub trait Codec {
+    /// Serializes `value`.
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, Error>;
+
+    /// Deserializes a value from `data`, possibly borrowing from it.
+    fn decode<'de, T: Deserialize<'de>>(data: &'de [u8]) -> Result<T, Error>;
+}
+
+/// JSON format using `serde_json` crate.
+#[derive(Debug, Clone, Copy)]
+pub struct Json;
+
+impl Codec for Json {
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode<'de, T: Deserialize<'de>>(data: &'de [u8]) -> Result<T, Error> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+/// Bincode format using `bincode` crate.
+#[cfg(feature = "bincode")]
+#[derive(Debug, Clone, Copy)]
+pub struct Bincode;
+
+#[cfg(feature = "bincode")]
+impl Codec for Bincode {
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn decode<'de, T: Deserialize<'de>>(data: &'de [u8]) -> Result<T, Error> {
+        Ok(bincode::deserialize(data)?)
+    }
+}
+
+/// CBOR format using `serde_cbor` crate.
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy)]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Codec for Cbor {
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+        Ok(serde_cbor::to_vec(value)?)
+    }
+
+    fn decode<'de, T: Deserialize<'de>>(data: &'de [u8]) -> Result<T, Error> {
+        Ok(serde_cbor::from_slice(data)?)
+    }
+}
+
+/// MessagePack format using `rmp-serde` crate.
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy)]
+pub struct MessagePack;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MessagePack {
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+        Ok(rmp_serde::to_vec(value)?)
+    }
+
+    fn decode<'de, T: Deserialize<'de>>(data: &'de [u8]) -> Result<T, Error> {
+        Ok(rmp_serde::from_slice(data)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Debug;
+
+    type Value = (String, Vec<u64>, Option<(bool, f64)>, Option<i8>);
+
+    fn value() -> Value {
+        (
+            "hello".to_owned(),
+            vec![0, 1, u64::MAX],
+            Some((true, 0.5)),
+            None,
+        )
+    }
+
+    fn round_trip<C: Codec, T>(value: &T)
+    where
+        T: Serialize + for<'de> Deserialize<'de> + PartialEq + Debug,
+    {
+        let data = C::encode(value).unwrap();
+        assert_eq!(&C::decode::<T>(&data).unwrap(), value);
+    }
+
+    fn rejects_truncated<C: Codec>() {
+        let data = C::encode(&value()).unwrap();
+        assert!(C::decode::<Value>(&data[..data.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn json() {
+        round_trip::<Json, _>(&value());
+        round_trip::<Json, _>(&());
+        let data = Json::encode(&"borrowed").unwrap();
+        assert_eq!(Json::decode::<&str>(&data).unwrap(), "borrowed");
+        rejects_truncated::<Json>();
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode() {
+        round_trip::<Bincode, _>(&value());
+        round_trip::<Bincode, _>(&());
+        let data = Bincode::encode(&"borrowed").unwrap();
+        assert_eq!(Bincode::decode::<&str>(&data).unwrap(), "borrowed");
+        rejects_truncated::<Bincode>();
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor() {
+        round_trip::<Cbor, _>(&value());
+        round_trip::<Cbor, _>(&());
+        let data = Cbor::encode(&"borrowed").unwrap();
+        assert_eq!(Cbor::decode::<&str>(&data).unwrap(), "borrowed");
+        rejects_truncated::<Cbor>();
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack() {
+        round_trip::<MessagePack, _>(&value());
+        round_trip::<MessagePack, _>(&());
+        let data = MessagePack::encode(&"borrowed").unwrap();
+        assert_eq!(MessagePack::decode::<&str>(&data).unwrap(), "borrowed");
+        rejects_truncated::<MessagePack>();
+    }
+}
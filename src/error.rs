@@ -0,0 +1,318 @@
+//! Errors returned by the expanded `gfaas::remote_fn`-annotated functions.
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    cell::RefCell,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::Once,
+};
+
+/// Error type returned by the expanded `gfaas::remote_fn`-annotated functions.
+///
+/// The variants let you tell apart failures which are worth retrying, such as
+/// [`Provider`](#variant.Provider), from those which are not, such as
+/// [`Serialization`](#variant.Serialization). Where available, the underlying cause is kept
+/// as an `anyhow::Error`, and returned by `std::error::Error::source` rather than printed
+/// along with the error.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Serializing the inputs or deserializing the output of the function failed.
+    Serialization(anyhow::Error),
+    /// The Wasm module of the function was not found, most likely because the app was not
+    /// built with `gfaas` build tool.
+    MissingModule(PathBuf),
+    /// No provider agreed to run the computation before the timeout.
+    MarketTimeout,
+    /// The provider didn't complete the computation before the timeout.
+    ExecutionTimeout,
+    /// The budget was insufficient to run the computation.
+    Budget(anyhow::Error),
+    /// The provider, or the local runtime, failed to run the function.
+    Provider(anyhow::Error),
+    /// The function panicked.
+    GuestPanic {
+        /// The panic message, or `Box<Any>` if the panic payload was neither a `&str` nor
+        /// a `String`, e.g., when panicking with `std::panic::panic_any`.
+        message: String,
+        /// Location in your sources where the panic occurred, if known. The panics in the
+        /// code generated around your function are reported at the location of the function.
+        location: Option<String>,
+        /// Backtrace of the panic, if captured, i.e., if `RUST_BACKTRACE` is set, and the
+        /// function is run natively or by a runtime which supports backtraces.
+        backtrace: Option<String>,
+        /// Whatever the function wrote to its standard error, if captured by the backend.
+        stderr: Option<String>,
+    },
+    /// Any other error.
+    Other(anyhow::Error),
+}
+
+impl Error {
+    /// Returns `true` if the failure may not happen again, and so invoking the function
+    /// again is worth a try.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::MarketTimeout | Self::ExecutionTimeout | Self::Provider(_)
+        )
+    }
+
+    /// Parses the failure record written by the panic hook of the Wasm module.
+    pub(crate) fn from_panic_record(record: &[u8], stderr: Option<String>) -> Self {
+        let record: serde_json::Value = match serde_json::from_slice(record) {
+            Ok(record) => record,
+            Err(err) => {
+                return Self::Serialization(
+                    anyhow::Error::new(err).context("deserializing guest panic record"),
+                )
+            }
+        };
+        let field = |name: &str| record[name].as_str().map(ToOwned::to_owned);
+        Self::GuestPanic {
+            message: field("message").unwrap_or_default(),
+            location: field("location"),
+            backtrace: field("backtrace"),
+            stderr,
+        }
+    }
+}
+
+thread_local! {
+    /// Location and backtrace of the panic of the function being run by `catch_panic` on this
+    /// thread, if any.
+    static CAUGHT: RefCell<Option<Caught>> = const { RefCell::new(None) };
+}
+
+#[derive(Default)]
+struct Caught {
+    location: Option<String>,
+    backtrace: Option<String>,
+}
+
+/// Runs the function `f` natively, turning its panic into [`Error::GuestPanic`]. The location
+/// and backtrace of the panic are only seen by the panic hook, so a hook recording them for
+/// `catch_panic` is installed on top of the existing one on the first call.
+///
+/// [`Error::GuestPanic`]: enum.Error.html#variant.GuestPanic
+pub fn catch_panic<T>(f: impl FnOnce() -> T) -> Result<T, Error> {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = CAUGHT.try_with(|caught| {
+                if let Some(caught) = caught.borrow_mut().as_mut() {
+                    caught.location = info.location().map(ToString::to_string);
+                    let backtrace = Backtrace::capture();
+                    if backtrace.status() == BacktraceStatus::Captured {
+                        caught.backtrace = Some(backtrace.to_string());
+                    }
+                }
+            });
+            hook(info);
+        }));
+    });
+
+    let outer = CAUGHT.with(|caught| caught.replace(Some(Caught::default())));
+    let res = panic::catch_unwind(AssertUnwindSafe(f));
+    let caught = CAUGHT
+        .with(|caught| caught.replace(outer))
+        .unwrap_or_default();
+    res.map_err(|payload| {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&str>() {
+                Ok(message) => (*message).to_owned(),
+                Err(_) => "Box<Any>".to_owned(),
+            },
+        };
+        Error::GuestPanic {
+            message,
+            location: caught.location,
+            backtrace: caught.backtrace,
+            stderr: None,
+        }
+    })
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        // Don't wrap the error twice if it went through `anyhow` on its way here.
+        match err.downcast::<Self>() {
+            Ok(err) => err,
+            Err(err) => Self::Other(err),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Serialization(_) => write!(f, "serialization failed"),
+            Self::MissingModule(path) => write!(
+                f,
+                "Wasm module '{}' not found; was the app built with gfaas build tool?",
+                path.display()
+            ),
+            Self::MarketTimeout => write!(f, "no provider agreed to run the computation in time"),
+            Self::ExecutionTimeout => write!(f, "provider didn't complete the computation in time"),
+            Self::Budget(_) => write!(f, "insufficient budget"),
+            Self::Provider(_) => write!(f, "provider failed"),
+            Self::GuestPanic {
+                message, location, ..
+            } => {
+                write!(f, "remote function panicked at '{}'", message)?;
+                if let Some(location) = location {
+                    write!(f, ", {}", location)?;
+                }
+                Ok(())
+            }
+            Self::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Serialization(err) | Self::Budget(err) | Self::Provider(err) => {
+                Some(err.as_ref())
+            }
+            Self::Other(err) => err.source(),
+            _ => None,
+        }
+    }
+}
+
+/// Error returned by the expanded `gfaas::remote_fn`-annotated functions which return
+/// `Result<T, E>` themselves.
+///
+/// It lets you tell apart the error `E` returned by your function when it ran to completion,
+/// and the failure to run it in the first place.
+#[derive(Debug)]
+pub enum RemoteError<E> {
+    /// The function ran to completion and returned an error.
+    Remote(E),
+    /// The function couldn't be run.
+    Dispatch(Error),
+}
+
+impl<E> RemoteError<E> {
+    /// Returns the error returned by the function, if any.
+    pub fn remote(self) -> Option<E> {
+        match self {
+            Self::Remote(err) => Some(err),
+            Self::Dispatch(_) => None,
+        }
+    }
+}
+
+impl<E> From<Error> for RemoteError<E> {
+    fn from(err: Error) -> Self {
+        Self::Dispatch(err)
+    }
+}
+
+impl<E> From<anyhow::Error> for RemoteError<E> {
+    fn from(err: anyhow::Error) -> Self {
+        Self::Dispatch(err.into())
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for RemoteError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Remote(err) => write!(f, "remote function returned an error: {}", err),
+            Self::Dispatch(err) => write!(f, "running remote function failed: {}", err),
+        }
+    }
+}
+
+impl<E> std::error::Error for RemoteError<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Remote(err) => Some(err),
+            Self::Dispatch(err) => Some(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_panic_record() {
+        let record = br#"{"message":"boom","location":"src/lib.rs:3:5","backtrace":"0: f"}"#;
+        let err = Error::from_panic_record(record, None);
+        assert_eq!(
+            err.to_string(),
+            "remote function panicked at 'boom', src/lib.rs:3:5"
+        );
+        match err {
+            Error::GuestPanic {
+                message,
+                location,
+                backtrace,
+                stderr,
+            } => {
+                assert_eq!(message, "boom");
+                assert_eq!(location.as_deref(), Some("src/lib.rs:3:5"));
+                assert_eq!(backtrace.as_deref(), Some("0: f"));
+                assert!(stderr.is_none());
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn parses_panic_record_without_message() {
+        match Error::from_panic_record(b"{}", None) {
+            Error::GuestPanic {
+                message, location, ..
+            } => {
+                assert_eq!(message, "");
+                assert!(location.is_none());
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn catches_panic() {
+        assert_eq!(catch_panic(|| 42).unwrap(), 42);
+        let line = line!() + 1;
+        match catch_panic(|| panic!("boom {}", 42)) {
+            Err(Error::GuestPanic {
+                message, location, ..
+            }) => {
+                assert_eq!(message, "boom 42");
+                assert_eq!(location, Some(format!("{}:{}:30", file!(), line)));
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        match catch_panic(|| std::panic::panic_any(42)) {
+            Err(Error::GuestPanic {
+                message, location, ..
+            }) => {
+                assert_eq!(message, "Box<Any>");
+                assert!(location.is_some());
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_panic_record() {
+        for record in &[&b""[..], b"boom", b"{\"message\""] {
+            match Error::from_panic_record(record, None) {
+                Error::Serialization(_) => {}
+                err => panic!("unexpected error: {:?}", err),
+            }
+        }
+    }
+}
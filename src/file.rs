@@ -0,0 +1,90 @@
+//! Files and directories passed to and from remote functions as is.
+
+use crate::Error;
+use anyhow::Context;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// File passed to or returned by a remote function without going through the codec.
+///
+/// As an argument, the local file is uploaded (or linked, if the backend runs on your machine)
+/// into the module's `/workdir`, and the function receives a `RemoteFile` pointing at it. As
+/// the output, the file the function points at is downloaded into a new temporary file, which
+/// is not removed automatically, so you're free to move it wherever you need it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteFile {
+    path: PathBuf,
+}
+
+impl RemoteFile {
+    /// Creates a new `RemoteFile` pointing at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Returns the path to the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Consumes the `RemoteFile`, returning the path to the file.
+    pub fn into_path_buf(self) -> PathBuf {
+        self.path
+    }
+
+    #[doc(hidden)]
+    pub fn from_output(data: Vec<u8>) -> Result<Self, Error> {
+        let mut file = tempfile::Builder::new()
+            .prefix("gfaas-")
+            .tempfile()
+            .context("creating temp file for output")?;
+        file.write_all(&data)
+            .context("writing output data to file")?;
+        let path = file
+            .into_temp_path()
+            .keep()
+            .context("persisting output file")?;
+        Ok(Self::new(path))
+    }
+}
+
+impl AsRef<Path> for RemoteFile {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Directory passed to a remote function without going through the codec.
+///
+/// Every file found in the local directory, including its subdirectories, is uploaded (or
+/// linked, if the backend runs on your machine) into the module's `/workdir`, and the function
+/// receives a `RemoteDir` pointing at their common root. Empty subdirectories are skipped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteDir {
+    path: PathBuf,
+}
+
+impl RemoteDir {
+    /// Creates a new `RemoteDir` pointing at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Returns the path to the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Consumes the `RemoteDir`, returning the path to the directory.
+    pub fn into_path_buf(self) -> PathBuf {
+        self.path
+    }
+}
+
+impl AsRef<Path> for RemoteDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
@@ -71,9 +71,283 @@
 //! run on top of some network of nodes: it may fail due to reasons not related to your app
 //! such as network downtime, etc.
 //!
+//! `gfaas::Error` tells you what exactly went wrong: for instance, `Error::Serialization` if
+//! the inputs or the output couldn't be (de)serialized, `Error::MarketTimeout` if no provider
+//! agreed to run the computation in time, `Error::ExecutionTimeout` if the provider didn't
+//! complete it in time, or `Error::Provider` if the provider failed to run it, in which case
+//! it is usually worth retrying. Should your function panic, you get back
+//! `Error::GuestPanic` carrying the panic message, as well as the standard error of the
+//! function if the backend was able to capture it. Only `&str` and `String` panic payloads
+//! make it to the message, any other payload is reported as `Box<Any>`. The error also carries
+//! the location of the panic within your sources, as long as your crate is built with Rust 1.88
+//! or newer, or else within the generated sources of the Wasm module, and its backtrace if
+//! `RUST_BACKTRACE` is set and the function runs natively.
+//!
 //! Furthermore, the input and output arguments of your function have to be serializable, and
 //! so they are expected to derive `serde::Serialize` and `serde::Deserialize` traits.
 //!
+//! Your function doesn't have to take any arguments, nor return anything, in which case the
+//! expanded function returns `Result<(), gfaas::Error>` once it completes. This comes in handy
+//! for functions generating their inputs themselves, or ones run for their side effects, e.g.,
+//! writing files in the native mode.
+//!
+//! ### Fallible functions
+//!
+//! If your function itself returns `Result<T, E>`, rather than nesting it in another `Result`,
+//! the expanded function flattens the two into `gfaas::RemoteError<E>`
+//!
+//! ```rust,ignore
+//! #[remote_fn]
+//! fn parse(input: String) -> Result<u64, String>;
+//! ```
+//!
+//! expands into
+//!
+//! ```rust,ignore
+//! async fn parse(input: String) -> Result<u64, gfaas::RemoteError<String>>;
+//! ```
+//!
+//! where `RemoteError::Remote(e)` carries the error returned by your function, while
+//! `RemoteError::Dispatch(e)` signals that the function couldn't be run in the first place.
+//! Naturally, `E` has to be serializable too. The `map` companion (see below) returns the
+//! `Result<T, E>` of every invocation as-is, and fails only if the batch couldn't be run.
+//!
+//! ### Running a function on many inputs
+//!
+//! A very common pattern is to split the workload into chunks and run the function on each
+//! of them. For this purpose, every annotated function comes with a `map` companion which
+//! takes an iterator of inputs (a tuple of arguments if the function takes more than one)
+//! and the maximum number of invocations which can run at the same time
+//!
+//! ```rust,ignore
+//! #[remote_fn]
+//! fn partial_sum(input: Vec<u64>) -> u64;
+//!
+//! let sums = partial_sum::map(vec![vec![1, 2], vec![3, 4]], 2).await?;
+//! assert_eq!(sums, vec![3, 7]);
+//! ```
+//!
+//! The outputs are returned in the same order as the inputs. When run on the Golem Network, all
+//! inputs are submitted at once, so that the market negotiation happens only once, and every
+//! agreement is reused for multiple inputs. The other backends run the invocations one after
+//! another, so the maximum is just an upper bound.
+//!
+//! Should the function panic on any of the inputs, the whole batch fails with the
+//! `Error::GuestPanic` of the first invocation to panic, so functions which are expected to fail
+//! on some inputs should rather return a `Result`.
+//!
+//! ### Calling functions repeatedly in a session
+//!
+//! Interactive apps tend to issue many small calls, one at a time, and paying the cost of
+//! preparing the backend, e.g., packaging and deploying the Wasm module, on every call quickly
+//! adds up. Instead, the calls can be made in a `gfaas::Session`, which prepares every module
+//! only once, and keeps the backend around until the session is closed. Every annotated
+//! function comes with a `call_in` companion for this purpose
+//!
+//! ```rust,ignore
+//! let session = gfaas::Session::builder()
+//!     .subnet("my_subnet")
+//!     .budget(10)
+//!     .build();
+//!
+//! for input in inputs {
+//!     let output = hello::call_in(&session, input).await?;
+//! }
+//! session.close().await;
+//! ```
+//!
+//! The configuration of the session, e.g., its subnet, budget or constraints on the providers,
+//! takes the place of the arguments to the attribute. When run on the Golem Network, the
+//! session keeps the agreement with the provider of every module, so that only the first call
+//! goes through the market negotiation. Closing the session ends the agreements and pays the
+//! providers.
+//!
+//! ### Generic functions
+//!
+//! Generic functions can be distributed too, as long as you list the types to instantiate them
+//! with using the `instantiate` argument, i.e., a type per instantiation, or a tuple of types
+//! if the function has more than one type parameter
+//!
+//! ```rust,ignore
+//! #[remote_fn(instantiate(u32, f64))]
+//! fn scale<T: Mul<Output = T> + Copy>(input: Vec<T>, factor: T) -> Vec<T>;
+//! ```
+//!
+//! Every instantiation gets its own Wasm module, and the expanded function picks the right one
+//! by the types it is called with. Calling it with any other types fails to compile. Lifetime
+//! parameters are fine too, as long as only the arguments borrow (the output can't).
+//!
+//! ### Functions associated with types
+//!
+//! If your code is organized around types, annotate an impl block with `gfaas::remote_impl`
+//! instead, which accepts the same arguments as `gfaas::remote_fn`. Every function of the block
+//! becomes a remote function, all of them compiled into a single Wasm module named after the type.
+//! Functions taking `&self` (or `self`) are fine too, as long as the type is serializable, since
+//! the receiver is sent over just like any other argument. The definition of the type, found in the
+//! same file as the impl block, needs the annotation as well, so that the Wasm module gets its
+//! copy
+//!
+//! ```rust,ignore
+//! use gfaas::remote_impl;
+//!
+//! #[remote_impl]
+//! #[derive(serde::Serialize, serde::Deserialize)]
+//! struct Point {
+//!     x: f64,
+//!     y: f64,
+//! }
+//!
+//! #[remote_impl]
+//! impl Point {
+//!     fn origin() -> Point;
+//!     fn norm(&self) -> f64;
+//!     #[local]
+//!     fn square(x: f64) -> f64;
+//! }
+//!
+//! let norm = Point { x: 3.0, y: 4.0 }.norm().await?;
+//! ```
+//!
+//! Functions marked with `#[local]` are left as they are, so that the other functions can call
+//! them both on the host and in the Wasm module. Since the Wasm module doesn't see your
+//! imports, refer to the derive macros by their full paths, and add `serde` with the `derive`
+//! feature to the dependencies of your functions (see below). Functions taking `&mut self` are
+//! unsupported, as are generic ones, and there is no `map` companion for any of them.
+//!
+//! ### Sharing a Wasm module between functions
+//!
+//! By default, every function is compiled into a Wasm module of its own. Functions given the
+//! same `group` argument share a single Wasm module instead, named after the group, which
+//! runs whichever function is called. This saves compiling (and uploading) the same
+//! dependencies over and over for functions which belong together anyway
+//!
+//! ```rust,ignore
+//! #[remote_fn(group = "geometry")]
+//! fn area(width: f64, height: f64) -> f64;
+//!
+//! #[remote_fn(group = "geometry")]
+//! fn perimeter(width: f64, height: f64) -> f64;
+//! ```
+//!
+//! ### Sharing types and helpers with the Wasm modules
+//!
+//! The Wasm module of a remote function gets a copy of the function only, so the function
+//! can't refer to the rest of your code. To get around this, put the function in an inline
+//! module annotated with `gfaas::remote_mod`, whose every item is then copied into the Wasm
+//! modules of the functions found directly in it. This lets you pass your own types around
+//! too, as long as they are serializable
+//!
+//! ```rust,ignore
+//! #[gfaas::remote_mod]
+//! mod shapes {
+//!     use gfaas::remote_fn;
+//!     use serde::{Deserialize, Serialize};
+//!
+//!     #[derive(Serialize, Deserialize)]
+//!     pub struct Rect {
+//!         pub width: f64,
+//!         pub height: f64,
+//!     }
+//!
+//!     fn area_of(rect: &Rect) -> f64 {
+//!         rect.width * rect.height
+//!     }
+//!
+//!     #[remote_fn]
+//!     pub fn area(rect: Rect) -> f64 {
+//!         area_of(&rect)
+//!     }
+//! }
+//!
+//! let area = shapes::area(shapes::Rect { width: 2.0, height: 3.0 }).await?;
+//! ```
+//!
+//! The module has to be self-contained: it can't refer to the items outside of it, e.g., via
+//! `super::`, and everything it imports, other than `gfaas`, has to be added to the
+//! dependencies of your functions (see below) so that it compiles for Wasm. The module is
+//! expected to be declared at the root of your crate, and its name to be unique across the
+//! crate.
+//!
+//! ### Choosing the serialization format
+//!
+//! By default, the inputs and the output of your function are serialized as JSON. For large
+//! binary payloads, you might prefer a more compact format, which can be selected with the
+//! `codec` argument, i.e., "json", "bincode", "cbor" or "msgpack"
+//!
+//! ```rust,ignore
+//! #[remote_fn(codec = "bincode")]
+//! fn compute_rectangle(rect: Rect) -> Vec<u32>;
+//! ```
+//!
+//! Other than JSON, each format requires enabling the cargo feature of the same name, i.e.,
+//! `bincode`, `cbor` or `msgpack`, while `gfaas` build tool adds the matching crate to the
+//! dependencies of your Wasm modules for you
+//!
+//! ```toml
+//! # Cargo.toml
+//! [dependencies]
+//! gfaas = { version = "0.3", features = ["bincode"] }
+//! ```
+//!
+//! ### Passing raw bytes
+//!
+//! Byte buffers go through the codec like any other value, which in case of JSON means they
+//! end up encoded as arrays of numbers. Instead, you can mark any `Vec<u8>` argument with
+//! `#[raw]` to pass it verbatim. The same goes for the output if you put `#[raw]` on the
+//! function itself
+//!
+//! ```rust,ignore
+//! #[remote_fn]
+//! #[raw]
+//! fn generate_proof(#[raw] params: Vec<u8>, preimage: Vec<u8>) -> Vec<u8>;
+//! ```
+//!
+//! ### Passing files and directories
+//!
+//! Large datasets don't have to be read into memory and serialized at all. If you take
+//! `gfaas::RemoteFile` or `gfaas::RemoteDir` as an argument, the file (or every file in the
+//! directory) is uploaded to the provider as is, or simply linked if the function runs on your
+//! machine, and your function receives the path to it inside the `/workdir` of the module.
+//! Returning `gfaas::RemoteFile` makes the file your function produced available on your
+//! machine, saved to a temporary file
+//!
+//! ```rust,ignore
+//! use gfaas::{remote_fn, RemoteDir, RemoteFile};
+//!
+//! #[remote_fn]
+//! fn count_words(text: RemoteFile, dictionaries: RemoteDir) -> RemoteFile {
+//!     let text = std::fs::read_to_string(text.path()).unwrap();
+//!     // ...
+//!     std::fs::write("/workdir/counts.csv", counts).unwrap();
+//!     RemoteFile::new("/workdir/counts.csv")
+//! }
+//! ```
+//!
+//! In the native mode, the function simply receives (and returns) the paths on your machine.
+//!
+//! ### Streaming outputs
+//!
+//! Rather than a single value, your function can return `impl Iterator<Item = T>`, in which case
+//! the items are written out one by one as they are produced instead of being collected into
+//! one giant output first. The expanded function returns a `gfaas::Stream<T>` which deserializes
+//! the items only once you get to them
+//!
+//! ```rust,ignore
+//! use futures::StreamExt;
+//!
+//! #[remote_fn]
+//! fn parse_logs(logs: RemoteFile) -> impl Iterator<Item = Record>;
+//!
+//! let mut records = parse_logs(logs).await?;
+//! while let Some(record) = records.next().await {
+//!     let record = record?;
+//!     // ...
+//! }
+//! ```
+//!
+//! In the native mode, the items are consumed while your function is still running. Other
+//! backends currently hand them over only once the function completes.
+//!
 //! ### Specifying Golem's configuration parameters
 //!
 //! You can currently set the following configuration parameters directly via `gfaas::remote_fn`
@@ -100,6 +374,33 @@
 //! fn hello(input: String) -> String;
 //! ```
 //!
+//! * number of retries should the provider fail to run the function or no provider run it
+//!   in time (defaults to 0), and the backoff between them, i.e., "none", "constant" (1 second,
+//!   the default) or "exponential":
+//!
+//! ```rust,ignore
+//! #[remote_fn(retries = 3, backoff = "exponential")]
+//! fn hello(input: String) -> String;
+//! ```
+//!
+//! * resources the provider has to offer, i.e., memory and storage in GiB (more than 0.5 and
+//!   1 by default), and the number of CPU threads:
+//!
+//! ```rust,ignore
+//! #[remote_fn(min_mem_gib = 4, min_storage_gib = 2, min_cpu_threads = 8)]
+//! fn prove(circuit: Vec<u8>) -> Vec<u8>;
+//! ```
+//!
+//! * any other constraints on the provider, given in the syntax of Golem's demands, i.e.,
+//!   parenthesized properties `(name)` or `(name<op>value)` where `<op>` is one of `=`, `<>`,
+//!   `<`, or `>`, and clauses `(&...)` or `(|...)` of such expressions, all of which have to be
+//!   satisfied:
+//!
+//! ```rust,ignore
+//! #[remote_fn(constraints = "(golem.inf.cpu.architecture=x86_64)")]
+//! fn hello(input: String) -> String;
+//! ```
+//!
 //! Of course, nobody stops you from setting any number of parameters at once
 //!
 //! ```rust,ignore
@@ -107,6 +408,17 @@
 //! fn hello(input: String) -> String;
 //! ```
 //!
+//! The retry policy for all of your functions can also be set at runtime, in which case the
+//! attribute arguments, if any, still take precedence
+//!
+//! ```rust,ignore
+//! gfaas::set_default_retry_policy(gfaas::RetryPolicy {
+//!     retries: 3,
+//!     backoff: gfaas::Backoff::exponential(),
+//!     ..Default::default()
+//! });
+//! ```
+//!
 //! ## Notes about `gfaas` build tool and adding dependecies for your functions
 //!
 //! The reason that a custom wrapper around `cargo` is needed, is because the function
@@ -116,7 +428,8 @@
 //! In addition, since the functions are cross-compiled to WASI, you need to install
 //! `wasm32-wasi` target in your used Rust toolchain. Furthermore, for that same reason, not
 //! all crates are compatible with WASI yet, but you can manually specify which crates you
-//! want your functions to depend on by adding a `[gfaas_dependencies]` section to your `Cargo.toml`
+//! want your functions to depend on in the `[package.metadata.gfaas]` section of your
+//! `Cargo.toml`
 //!
 //! ```toml
 //! # Cargo.toml
@@ -126,10 +439,69 @@
 //! [dependecies]
 //! actix = "1"
 //!
-//! [gfaas_dependencies]
+//! [package.metadata.gfaas.dependencies]
 //! log = "0.4"
 //! ```
 //!
+//! Paths of path dependencies are relative to your `Cargo.toml`, just like those of regular
+//! dependencies, and dependencies inherited from the workspace, i.e., `{ workspace = true }`,
+//! are taken from its `[workspace.dependencies]`. If the code shared by your functions lives in
+//! a crate of its own, e.g., one of your workspace, point `guest_crate` at it instead, and its
+//! public API becomes available to all of your functions
+//!
+//! ```toml
+//! # Cargo.toml
+//! [package.metadata.gfaas]
+//! guest_crate = "../kernels"
+//! ```
+//!
+//! ```rust,ignore
+//! #[remote_fn]
+//! fn fft(samples: Vec<f64>) -> Vec<f64> {
+//!     kernels::fft(&samples)
+//! }
+//! ```
+//!
+//! Just like the dependencies, the guest crate has to compile for `wasm32-wasi`, and has to be a
+//! dependency of your package as well.
+//!
+//! The same section holds the defaults of `codec`, `subnet` and `budget` for all of the
+//! functions of your package, which their attributes can still override, and the `profile`s the
+//! Wasm modules are built with, since those of your workspace don't apply to them
+//!
+//! ```toml
+//! # Cargo.toml
+//! [package.metadata.gfaas]
+//! codec = "bincode"
+//! subnet = "devnet-alpha.2"
+//! budget = 100
+//!
+//! [package.metadata.gfaas.profile.release]
+//! opt-level = "s"
+//! lto = true
+//! ```
+//!
+//! Every package of your workspace gets Wasm modules of its own, built according to its own
+//! settings. The Wasm modules are named after the crate and the function, followed by the hash of
+//! the location of the function in your sources, e.g., `app-compute-1f2e3d4c.wasm`, so that
+//! functions of the same name in different modules or packages don't clobber each other. Macros
+//! only get to know the locations since Rust 1.88, so building the Wasm modules requires Rust 1.88
+//! or newer. The functions of a group are told apart by their names only, and so should two
+//! functions of the same name end up in the same group, the build fails, asking you to rename
+//! either of them. The `[gfaas_dependencies]` section at the root of your workspace, which previous
+//! versions used, is still read, but deprecated.
+//!
+//! The dependencies shared by your functions and your project, e.g., `serde_json`, are pinned to
+//! the versions in your `Cargo.lock`, which the lockfile of the Wasm modules is seeded with
+//! before every build, and you get a warning if any of them still ends up at a different
+//! version. For reproducible builds, pass `--locked` (or `--frozen`) to `gfaas build`, which then
+//! fails unless every dependency of your functions is locked by your `Cargo.lock`, while
+//! `--offline` keeps both builds off the network
+//!
+//! ```sh
+//! gfaas build --release --locked
+//! ```
+//!
 //! ## Notes on running your app locally (for testing)
 //!
 //! It is well known that prior to launching our app on some distributed network of nodes, it
@@ -146,98 +518,122 @@
 //! so you can verify that everything works as expected prior to launching the tasks on the
 //! Golem Network.
 //!
+//! If you call your functions a lot, e.g., in a test suite, you might prefer
+//! `backend = "embedded"` instead. Rather than packaging and deploying the Wasm module on every
+//! call, it compiles the module once and instantiates it directly in-process, which is much
+//! faster.
+//!
+//! The attribute only provides a default though, and the backend can also be selected at
+//! runtime without recompiling your app. This is handy when you want to test locally the very
+//! same binary you are going to launch on the Golem Network. You can either set the
+//! `GFAAS_BACKEND` env variable to one of `embedded`, `local`, `yagna` or `mock`
+//!
+//! ```sh
+//! GFAAS_BACKEND=local gfaas run
+//! ```
+//!
+//! or select the backend programmatically (the env variable still takes precedence)
+//!
+//! ```rust,ignore
+//! gfaas::set_default_backend::<gfaas::backend::Local>();
+//! ```
+//!
+//! ### Native mode
+//!
+//! Finally, you can skip Wasm altogether and run the body of your function natively, in a
+//! separate thread on your machine. The inputs and output still go through the same
+//! serialization round-trip, so any serialization bugs will still surface. Request it for
+//! a single function with
+//!
+//! ```rust,ignore
+//! #[remote_fn(native = true)]
+//! fn hello(input: String) -> String;
+//! ```
+//!
+//! or for all of them with the `native` feature of `gfaas`, which lets you unit test your
+//! functions with plain `cargo`, without the `wasm32-wasi` target installed
+//!
+//! ```sh
+//! cargo test --features gfaas/native
+//! ```
+//!
+//! Otherwise, a project built without `gfaas` build tool has no Wasm modules, and so its
+//! functions fail with `gfaas::Error::MissingModule`, unless they are run by a backend which
+//! doesn't need the modules, i.e., `gfaas::backend::Mock`.
+//!
+//! Note that in the native mode your function is compiled for the host, so any crates it
+//! uses from `[package.metadata.gfaas.dependencies]` have to be your (dev-)dependencies as well.
+//!
+//! ## Custom backends
+//!
+//! Under-the-hood, every expanded function serializes its arguments and hands them over to
+//! `gfaas::dispatch` which drives an implementation of the `gfaas::Backend` trait: it prepares
+//! the package containing the Wasm module, uploads the inputs, runs the entry point and downloads
+//! the output. `backend = "local"` (or `run_local = true`) selects `gfaas::backend::Local`,
+//! `backend = "mock"` selects `gfaas::backend::Mock`, while the default is
+//! `gfaas::backend::Yagna`. If none suits you, you can implement the trait yourself
+//!
+//! ```rust,ignore
+//! use gfaas::{async_trait, Backend, Config, Error};
+//! use std::path::Path;
+//!
+//! struct MyBackend;
+//!
+//! #[async_trait(?Send)]
+//! impl Backend for MyBackend {
+//!     fn new(config: &Config) -> Result<Self, Error> { todo!() }
+//!     async fn prepare(&mut self, module: &Path) -> Result<(), Error> { todo!() }
+//!     async fn upload(&mut self, name: &str, data: Vec<u8>) -> Result<(), Error> { todo!() }
+//!     async fn run(&mut self, entry_point: &str, args: Vec<String>, outputs: &[&str]) -> Result<(), Error> { todo!() }
+//!     async fn download(&mut self, name: &str) -> Result<Vec<u8>, Error> { todo!() }
+//! }
+//! ```
+//!
+//! and point the function at it using the `backend` attribute
+//!
+//! ```rust,ignore
+//! #[remote_fn(backend = "crate::MyBackend")]
+//! fn hello(input: String) -> String;
+//! ```
+//!
 //! ## Examples
 //!
 //! A couple illustrative examples of how to use this crate can be found in the `examples/`
 //! directory. All examples require `gfaas` build tool to be built.
 
+pub mod backend;
+pub mod codec;
+mod error;
+mod file;
+mod package;
+mod retry;
+mod session;
+mod stream;
+
+pub use backend::{dispatch, dispatch_batch, set_default_backend, Backend, Config};
+pub use codec::Codec;
+pub use error::{Error, RemoteError};
+pub use file::{RemoteDir, RemoteFile};
+pub use retry::{set_default_retry_policy, Backoff, RetryPolicy};
+pub use session::{Session, SessionBuilder};
+pub use stream::Stream;
+
+/// Re-export of `async_trait::async_trait` for convenience when implementing custom
+/// [`Backend`]s.
+///
+/// [`Backend`]: backend/trait.Backend.html
+pub use async_trait::async_trait;
+
 pub mod __private {
     //! This is a private module. The stability of this API is not guaranteed and may change
     //! without notice in the future.
+    pub use crate::error::catch_panic;
+    pub use crate::stream::{from_channel, from_records};
     pub use anyhow;
     pub use futures;
+    pub use serde;
     pub use serde_json;
-    pub use tempfile;
     pub use tokio;
-    pub use ya_agreement_utils;
-    pub use ya_runtime_wasi;
-    pub use yarapi;
-
-    #[allow(unused)]
-    pub mod package {
-        //! This private module describes the structures concerning Yagna packages.
-        use anyhow::Result;
-        use std::{
-            fs,
-            io::{Cursor, Write},
-            path::Path,
-        };
-        use zip::{write::FileOptions, CompressionMethod, ZipWriter};
-
-        /// Represents Yagna package which internally is represented as a zip archive.
-        pub struct Package {
-            zip_writer: ZipWriter<Cursor<Vec<u8>>>,
-            options: FileOptions,
-            module_name: Option<String>,
-        }
-
-        impl Package {
-            /// Creates new empty Yagna package.
-            pub fn new() -> Self {
-                let options = FileOptions::default().compression_method(CompressionMethod::Stored);
-                let zip_writer = ZipWriter::new(Cursor::new(Vec::new()));
-
-                Self {
-                    zip_writer,
-                    options,
-                    module_name: None,
-                }
-            }
-
-            /// Adds a Wasm modules from path.
-            pub fn add_module_from_path<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-                let module_name = path
-                    .as_ref()
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_owned();
-                let contents = fs::read(path.as_ref())?;
-                self.zip_writer
-                    .start_file(&module_name, self.options.clone())?;
-                self.zip_writer.write(&contents)?;
-                self.module_name = Some(module_name);
-
-                Ok(())
-            }
-
-            /// Write the package to file at the given path.
-            pub fn write<P: AsRef<Path>>(mut self, path: P) -> Result<()> {
-                // create manifest
-                let comps: Vec<_> = self.module_name.as_ref().unwrap().split('.').collect();
-                let manifest = serde_json::json!({
-                    "id": "custom",
-                    "name": "custom",
-                    "entry-points": [{
-                        "id": comps[0],
-                        "wasm-path": self.module_name.unwrap(),
-                    }],
-                    "mount-points": [{
-                        "rw": "workdir",
-                    }]
-                });
-                self.zip_writer
-                    .start_file("manifest.json", self.options.clone())?;
-                self.zip_writer.write(&serde_json::to_vec(&manifest)?)?;
-
-                let finalized = self.zip_writer.finish()?.into_inner();
-                fs::write(path.as_ref(), finalized)?;
-
-                Ok(())
-            }
-        }
-    }
 }
 
 /// The bread and butter of this crate.
@@ -257,6 +653,46 @@ pub mod __private {
 /// ```
 pub use gfaas_macro::remote_fn;
 
-/// Re-export of `anyhow::Error` which is the default type returned by the expanded
-/// `gfaas::remote_fn`-annotated function.
-pub use anyhow::Error;
+/// Turns the functions of an inherent impl block into remote functions, which are all compiled
+/// into a single Wasm module named after the type.
+///
+/// The definition of the type has to be annotated as well, so that the Wasm module gets a copy
+/// of it. See [crate-level docs](index.html) for details.
+///
+/// ```rust,ignore
+/// use gfaas::remote_impl;
+///
+/// #[remote_impl]
+/// #[derive(serde::Serialize, serde::Deserialize)]
+/// struct Point {
+///     x: f64,
+///     y: f64,
+/// }
+///
+/// #[remote_impl(budget = 10)]
+/// impl Point {
+///     fn norm(&self) -> f64;
+/// }
+/// ```
+pub use gfaas_macro::remote_impl;
+
+/// Copies an inline module into the Wasm modules of the remote functions found directly in it,
+/// so that they can use its types and helpers.
+///
+/// The module itself takes no arguments: pass them to its `remote_fn`s instead. See
+/// [crate-level docs](index.html) for details.
+///
+/// ```rust,ignore
+/// #[gfaas::remote_mod]
+/// mod shapes {
+///     #[derive(serde::Serialize, serde::Deserialize)]
+///     pub struct Rect {
+///         pub width: f64,
+///         pub height: f64,
+///     }
+///
+///     #[gfaas::remote_fn(budget = 10)]
+///     pub fn area(rect: Rect) -> f64;
+/// }
+/// ```
+pub use gfaas_macro::remote_mod;
@@ -0,0 +1,140 @@
+//! This module describes the structures concerning Yagna packages.
+use anyhow::{anyhow, Result};
+use std::{
+    fs,
+    io::{Cursor, Write},
+    path::Path,
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+/// Represents Yagna package which internally is represented as a zip archive.
+///
+/// Every Wasm module added to the package gets its own entry point named after the module,
+/// e.g., `hello` for `hello.wasm`.
+pub struct Package {
+    zip_writer: ZipWriter<Cursor<Vec<u8>>>,
+    options: FileOptions,
+    module_names: Vec<String>,
+}
+
+impl Package {
+    /// Creates new empty Yagna package.
+    pub fn new() -> Self {
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let zip_writer = ZipWriter::new(Cursor::new(Vec::new()));
+
+        Self {
+            zip_writer,
+            options,
+            module_names: vec![],
+        }
+    }
+
+    /// Adds a Wasm module from path.
+    pub fn add_module_from_path<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let module_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("invalid Wasm module path '{}'", path.display()))?
+            .to_owned();
+        if self.module_names.contains(&module_name) {
+            return Err(anyhow!("Wasm module '{}' already added", module_name));
+        }
+        let contents = fs::read(path)?;
+        self.zip_writer.start_file(&module_name, self.options)?;
+        self.zip_writer.write_all(&contents)?;
+        self.module_names.push(module_name);
+
+        Ok(())
+    }
+
+    /// Write the package to file at the given path.
+    pub fn write<P: AsRef<Path>>(mut self, path: P) -> Result<()> {
+        // create manifest
+        if self.module_names.is_empty() {
+            return Err(anyhow!("package without any Wasm modules"));
+        }
+        let entry_points: Vec<_> = self
+            .module_names
+            .iter()
+            .map(|name| {
+                serde_json::json!({
+                    "id": name.split('.').next().unwrap(),
+                    "wasm-path": name,
+                })
+            })
+            .collect();
+        let manifest = serde_json::json!({
+            "id": "custom",
+            "name": "custom",
+            "entry-points": entry_points,
+            "mount-points": [{
+                "rw": "workdir",
+            }]
+        });
+        self.zip_writer.start_file("manifest.json", self.options)?;
+        self.zip_writer.write_all(&serde_json::to_vec(&manifest)?)?;
+
+        let finalized = self.zip_writer.finish()?.into_inner();
+        fs::write(path.as_ref(), finalized)?;
+
+        Ok(())
+    }
+}
+
+impl Default for Package {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use tempfile::tempdir;
+    use zip::ZipArchive;
+
+    #[test]
+    fn writes_entry_point_per_module() -> Result<()> {
+        let dir = tempdir()?;
+        fs::write(dir.path().join("app-hello.wasm"), b"hello")?;
+        fs::write(dir.path().join("app-group.wasm"), b"group")?;
+        let mut package = Package::new();
+        package.add_module_from_path(dir.path().join("app-hello.wasm"))?;
+        package.add_module_from_path(dir.path().join("app-group.wasm"))?;
+        assert!(package
+            .add_module_from_path(dir.path().join("app-hello.wasm"))
+            .is_err());
+        let package_path = dir.path().join("pkg.zip");
+        package.write(&package_path)?;
+
+        let mut archive = ZipArchive::new(fs::File::open(&package_path)?)?;
+        let mut contents = String::new();
+        archive
+            .by_name("app-group.wasm")?
+            .read_to_string(&mut contents)?;
+        assert_eq!(contents, "group");
+        let manifest: serde_json::Value =
+            serde_json::from_reader(archive.by_name("manifest.json")?)?;
+        assert_eq!(
+            manifest["entry-points"],
+            serde_json::json!([
+                { "id": "app-hello", "wasm-path": "app-hello.wasm" },
+                { "id": "app-group", "wasm-path": "app-group.wasm" },
+            ])
+        );
+        assert_eq!(
+            manifest["mount-points"],
+            serde_json::json!([{ "rw": "workdir" }])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_empty_package() {
+        let dir = tempdir().unwrap();
+        assert!(Package::new().write(dir.path().join("pkg.zip")).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+//! Retrying failed invocations of remote functions.
+use crate::{backend::Backend, Config, Error};
+use std::{cmp, sync::RwLock, time::Duration};
+use tokio::time;
+
+/// Strategy for spacing out the retries of a failed remote function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Backoff {
+    /// Retry straight away.
+    None,
+    /// Wait the same amount of time before every retry.
+    Constant(Duration),
+    /// Wait `initial` before the first retry, and twice as long before every subsequent one,
+    /// but never longer than `max`.
+    Exponential {
+        /// Delay before the first retry.
+        initial: Duration,
+        /// Upper bound on the delay.
+        max: Duration,
+    },
+}
+
+impl Backoff {
+    /// Exponential backoff starting at 1 second, and capped at 1 minute.
+    pub fn exponential() -> Self {
+        Self::Exponential {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+        }
+    }
+
+    /// Returns the delay before the `retry`-th retry, counting from zero.
+    pub fn delay(&self, retry: u32) -> Duration {
+        match *self {
+            Self::None => Duration::from_secs(0),
+            Self::Constant(delay) => delay,
+            Self::Exponential { initial, max } => {
+                let factor = 2u32.saturating_pow(retry);
+                cmp::min(initial.checked_mul(factor).unwrap_or(max), max)
+            }
+        }
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::Constant(Duration::from_secs(1))
+    }
+}
+
+/// Policy of retrying failed invocations of remote functions.
+///
+/// Only failures which may not happen again, i.e., those for which [`Error::is_retryable`]
+/// returns `true`, are retried. When invoked on a batch of inputs, the whole batch is retried.
+///
+/// [`Error::is_retryable`]: enum.Error.html#method.is_retryable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of retries once the first attempt failed (defaults to 0).
+    pub retries: u32,
+    /// Strategy for spacing out the retries (defaults to 1 second between retries).
+    pub backoff: Backoff,
+    /// Whether the providers which failed to run the function should be excluded from the
+    /// subsequent attempts (defaults to `true`).
+    pub exclude_failed_providers: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            backoff: Backoff::default(),
+            exclude_failed_providers: true,
+        }
+    }
+}
+
+static DEFAULT_RETRY_POLICY: RwLock<Option<RetryPolicy>> = RwLock::new(None);
+
+/// Sets the retry policy for all remote functions.
+///
+/// The `retries` and `backoff` arguments to `gfaas::remote_fn` attribute, if any, still take
+/// precedence.
+pub fn set_default_retry_policy(policy: RetryPolicy) {
+    *DEFAULT_RETRY_POLICY.write().unwrap() = Some(policy);
+}
+
+pub(crate) fn default_retry_policy() -> RetryPolicy {
+    DEFAULT_RETRY_POLICY
+        .read()
+        .unwrap()
+        .clone()
+        .unwrap_or_default()
+}
+
+/// Decides whether the `retry`-th retry should follow the failure `err` of the `backend`,
+/// and if so, excludes the failed providers from `config` and waits out the backoff.
+pub(crate) async fn before_retry(
+    config: &mut Config,
+    backend: &mut dyn Backend,
+    err: &Error,
+    retry: u32,
+) -> bool {
+    let policy = &config.retry;
+    if retry >= policy.retries || !err.is_retryable() {
+        return false;
+    }
+    let delay = policy.backoff.delay(retry);
+    if policy.exclude_failed_providers {
+        config.excluded_providers.extend(backend.failed_providers());
+    }
+    time::delay_for(delay).await;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use async_trait::async_trait;
+    use std::path::Path;
+
+    /// Backend which only ever reports the provider `node` as failed.
+    struct Failing;
+
+    #[async_trait(?Send)]
+    impl Backend for Failing {
+        fn new(_config: &Config) -> Result<Self, Error> {
+            Ok(Self)
+        }
+
+        async fn prepare(&mut self, _module: &Path) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn upload(&mut self, _name: &str, _data: Vec<u8>) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn run(
+            &mut self,
+            _entry_point: &str,
+            _args: Vec<String>,
+            _outputs: &[&str],
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn download(&mut self, _name: &str) -> Result<Vec<u8>, Error> {
+            Ok(vec![])
+        }
+
+        fn failed_providers(&mut self) -> Vec<String> {
+            vec!["node".to_owned()]
+        }
+    }
+
+    fn config(retries: u32, exclude_failed_providers: bool) -> Config {
+        Config {
+            retry: RetryPolicy {
+                retries,
+                backoff: Backoff::None,
+                exclude_failed_providers,
+            },
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn constant_delay() {
+        let backoff = Backoff::Constant(Duration::from_secs(2));
+        assert_eq!(backoff.delay(0), Duration::from_secs(2));
+        assert_eq!(backoff.delay(10), Duration::from_secs(2));
+        assert_eq!(Backoff::None.delay(3), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn exponential_delay() {
+        let backoff = Backoff::Exponential {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(10),
+        };
+        let delays: Vec<_> = (0..6).map(|retry| backoff.delay(retry).as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+        // The factor overflows long before the retries run out.
+        assert_eq!(backoff.delay(40), Duration::from_secs(10));
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(10));
+    }
+
+    #[actix_rt::test]
+    async fn retries_up_to_max_retries() {
+        let mut config = config(2, false);
+        let err = Error::Provider(anyhow!("boom"));
+        assert!(before_retry(&mut config, &mut Failing, &err, 0).await);
+        assert!(before_retry(&mut config, &mut Failing, &err, 1).await);
+        assert!(!before_retry(&mut config, &mut Failing, &err, 2).await);
+        assert!(config.excluded_providers.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn retries_only_retryable_errors() {
+        let mut config = config(2, true);
+        assert!(before_retry(&mut config, &mut Failing, &Error::MarketTimeout, 0).await);
+        let err = Error::Serialization(anyhow!("boom"));
+        assert!(!before_retry(&mut config, &mut Failing, &err, 0).await);
+        let err = Error::GuestPanic {
+            message: "boom".to_owned(),
+            location: None,
+            backtrace: None,
+            stderr: None,
+        };
+        assert!(!before_retry(&mut config, &mut Failing, &err, 0).await);
+    }
+
+    #[actix_rt::test]
+    async fn excludes_failed_providers() {
+        let mut config = config(1, true);
+        let err = Error::Provider(anyhow!("boom"));
+        assert!(before_retry(&mut config, &mut Failing, &err, 0).await);
+        assert_eq!(config.excluded_providers, vec!["node".to_owned()]);
+        // Nothing is excluded once there are no retries left.
+        assert!(!before_retry(&mut config, &mut Failing, &err, 1).await);
+        assert_eq!(config.excluded_providers, vec!["node".to_owned()]);
+    }
+}
@@ -0,0 +1,388 @@
+//! Sessions reusing the prepared backends across the invocations of remote functions.
+use crate::{
+    backend::{
+        create, invoke, module_path, select, split_name, unpack_output, Backend, Config, Factory,
+        Input,
+    },
+    retry::before_retry,
+    Error, RetryPolicy,
+};
+use actix_rt::Arbiter;
+use futures::lock::Mutex;
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc, time::Duration};
+
+/// Backend prepared for a single Wasm module, if any yet.
+type Slot = Rc<Mutex<Option<Box<dyn Backend>>>>;
+
+/// Context in which remote functions can be invoked repeatedly, without preparing the backend
+/// from scratch every time.
+///
+/// Every Wasm module is prepared once, i.e., on the first call to any of its functions, and
+/// the backend instance is then kept until the session is closed with [`close`], unless it
+/// fails in the meantime, in which case it is released and prepared anew on the next call. See
+/// [crate-level docs](index.html) for details.
+///
+/// Every `gfaas::remote_fn`-annotated function comes with a `call_in` companion which invokes
+/// it in a session
+///
+/// ```rust,ignore
+/// let session = gfaas::Session::builder()
+///     .subnet("my_subnet")
+///     .budget(10)
+///     .build();
+///
+/// for input in inputs {
+///     let output = hello::call_in(&session, input).await?;
+/// }
+/// session.close().await;
+/// ```
+///
+/// The configuration of the session takes the place of the arguments to the attribute, e.g.,
+/// `budget` or `retries`. Calls to the functions of the same module are run one after another,
+/// while those of different modules may run at the same time.
+///
+/// A session which is dropped rather than closed releases its backends in the background,
+/// provided it's dropped within an actix system, and doesn't release them otherwise.
+///
+/// [`close`]: #method.close
+pub struct Session {
+    config: RefCell<Config>,
+    factory: Option<Factory>,
+    slots: RefCell<BTreeMap<String, Slot>>,
+}
+
+impl Session {
+    /// Creates a new session with the default configuration.
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    /// Returns a builder of a new session.
+    pub fn builder() -> SessionBuilder {
+        SessionBuilder::default()
+    }
+
+    /// Returns the configuration of the session.
+    pub fn config(&self) -> Config {
+        self.config.borrow().clone()
+    }
+
+    /// Executes the remote function `name` using the backend prepared for its module in this
+    /// session, or a new instance of `B` unless a different backend was selected.
+    ///
+    /// This function is what the `call_in` companion of every `gfaas::remote_fn`-annotated
+    /// function expands into. See [`dispatch`] for details.
+    ///
+    /// [`dispatch`]: fn.dispatch.html
+    pub async fn dispatch<B: Backend + 'static>(
+        &self,
+        name: &str,
+        inputs: Vec<Input>,
+    ) -> Result<Vec<u8>, Error> {
+        let (module, function) = split_name(name);
+        let wasm = module_path(module)?;
+        let factory = select::<B>(self.factory)?;
+        let slot = self
+            .slots
+            .borrow_mut()
+            .entry(module.to_owned())
+            .or_default()
+            .clone();
+        let mut slot = slot.lock().await;
+        let mut config = self.config();
+        let mut retry = 0;
+        loop {
+            let (mut backend, mut prepared) = match slot.take() {
+                Some(backend) => (backend, true),
+                None => (factory(&config)?, false),
+            };
+            let res = async {
+                if !prepared {
+                    backend.prepare(&wasm).await?;
+                    prepared = true;
+                }
+                let output = invoke(&mut *backend, module, function, inputs.clone()).await?;
+                unpack_output(output, backend.take_stderr())
+            }
+            .await;
+            match res {
+                // The backend failed rather than the function, so it is not to be trusted with
+                // the next invocation.
+                Err(err) if err.is_retryable() => {
+                    backend.release().await;
+                    if !before_retry(&mut config, &mut *backend, &err, retry).await {
+                        return Err(err);
+                    }
+                    self.config.borrow_mut().excluded_providers = config.excluded_providers.clone();
+                    retry += 1;
+                }
+                // The outcome of the function stands even if the backend fails to reset, but
+                // the backend is then given up on rather than kept for the next invocation.
+                res => {
+                    if prepared && reset(&mut *backend, module).await {
+                        *slot = Some(backend);
+                    } else {
+                        backend.release().await;
+                    }
+                    return res;
+                }
+            }
+        }
+    }
+
+    /// Closes the session, releasing the backends prepared in it, e.g., the agreements with
+    /// the providers on the Golem Network.
+    pub async fn close(self) {
+        for mut backend in self.take_backends() {
+            backend.release().await;
+        }
+    }
+
+    fn take_backends(&self) -> Vec<Box<dyn Backend>> {
+        let slots = std::mem::take(&mut *self.slots.borrow_mut());
+        slots
+            .values()
+            .filter_map(|slot| slot.try_lock()?.take())
+            .collect()
+    }
+}
+
+/// Resets the `backend` prepared for the `module`, and returns whether it can be reused.
+async fn reset(backend: &mut dyn Backend, module: &str) -> bool {
+    match backend.reset().await {
+        Ok(()) => true,
+        Err(err) => {
+            log::warn!("failed to reset the backend of '{}': {}", module, err);
+            false
+        }
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let backends = self.take_backends();
+        if !backends.is_empty() && Arbiter::is_running() {
+            Arbiter::spawn(async move {
+                for mut backend in backends {
+                    backend.release().await;
+                }
+            });
+        }
+    }
+}
+
+/// Builder of a [`Session`].
+///
+/// [`Session`]: struct.Session.html
+#[derive(Default)]
+pub struct SessionBuilder {
+    config: Config,
+    factory: Option<Factory>,
+}
+
+impl SessionBuilder {
+    /// Sets the subnet tag.
+    pub fn subnet(mut self, subnet: impl Into<String>) -> Self {
+        self.config.subnet = subnet.into();
+        self
+    }
+
+    /// Sets the maximum budget in NGNT.
+    pub fn budget(mut self, budget: u64) -> Self {
+        self.config.budget = budget;
+        self
+    }
+
+    /// Sets the timeout of the computation.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
+
+    /// Sets the policy of retrying failed invocations.
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.config.retry = retry;
+        self
+    }
+
+    /// Excludes the provider with the node id `node_id` from running the functions.
+    pub fn exclude_provider(mut self, node_id: impl Into<String>) -> Self {
+        self.config.excluded_providers.push(node_id.into());
+        self
+    }
+
+    /// Sets the memory the provider has to offer, in GiB: more than `gib`.
+    pub fn min_mem_gib(mut self, gib: f64) -> Self {
+        self.config.min_mem_gib = gib;
+        self
+    }
+
+    /// Sets the storage the provider has to offer, in GiB: more than `gib`.
+    pub fn min_storage_gib(mut self, gib: f64) -> Self {
+        self.config.min_storage_gib = gib;
+        self
+    }
+
+    /// Sets the minimum number of CPU threads the provider has to offer.
+    pub fn min_cpu_threads(mut self, threads: u32) -> Self {
+        self.config.min_cpu_threads = Some(threads);
+        self
+    }
+
+    /// Adds the `constraints` on the provider, given in the syntax of Golem's demands, e.g.,
+    /// `(golem.inf.cpu.architecture=x86_64)`. Malformed constraints fail the calls made in
+    /// the session.
+    pub fn constraints(mut self, constraints: impl Into<String>) -> Self {
+        self.config.constraints.push(constraints.into());
+        self
+    }
+
+    /// Runs the functions with the backend `B`, regardless of the backend specified in their
+    /// attributes. Note that the backend named by the `GFAAS_BACKEND` env variable, if any,
+    /// still takes precedence.
+    pub fn backend<B: Backend + 'static>(mut self) -> Self {
+        self.factory = Some(create::<B>);
+        self
+    }
+
+    /// Builds the session.
+    pub fn build(self) -> Session {
+        Session {
+            config: RefCell::new(self.config),
+            factory: self.factory,
+            slots: RefCell::new(BTreeMap::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::OUTPUT_TAG;
+    use async_trait::async_trait;
+    use std::{
+        path::Path,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    static PREPARED: AtomicUsize = AtomicUsize::new(0);
+    static RELEASED: AtomicUsize = AtomicUsize::new(0);
+
+    /// Backend counting how many times it was prepared and released.
+    struct Counting;
+
+    #[async_trait(?Send)]
+    impl Backend for Counting {
+        fn new(_config: &Config) -> Result<Self, Error> {
+            Ok(Self)
+        }
+
+        async fn prepare(&mut self, _module: &Path) -> Result<(), Error> {
+            PREPARED.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn upload(&mut self, _name: &str, _data: Vec<u8>) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn run(
+            &mut self,
+            _entry_point: &str,
+            _args: Vec<String>,
+            _outputs: &[&str],
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn download(&mut self, _name: &str) -> Result<Vec<u8>, Error> {
+            Ok(vec![OUTPUT_TAG, 42])
+        }
+
+        async fn release(&mut self) {
+            RELEASED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    static RESET_PREPARED: AtomicUsize = AtomicUsize::new(0);
+    static RESET_RELEASED: AtomicUsize = AtomicUsize::new(0);
+
+    /// Backend which fails to reset after every invocation.
+    struct FailingReset;
+
+    #[async_trait(?Send)]
+    impl Backend for FailingReset {
+        fn new(_config: &Config) -> Result<Self, Error> {
+            Ok(Self)
+        }
+
+        async fn prepare(&mut self, _module: &Path) -> Result<(), Error> {
+            RESET_PREPARED.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn upload(&mut self, _name: &str, _data: Vec<u8>) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn run(
+            &mut self,
+            _entry_point: &str,
+            _args: Vec<String>,
+            _outputs: &[&str],
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn download(&mut self, _name: &str) -> Result<Vec<u8>, Error> {
+            Ok(vec![OUTPUT_TAG, 42])
+        }
+
+        async fn reset(&mut self) -> Result<(), Error> {
+            Err(Error::Other(anyhow::anyhow!("workdir is gone")))
+        }
+
+        async fn release(&mut self) {
+            RESET_RELEASED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn releases_backend_failing_to_reset() {
+        let session = Session::builder().backend::<FailingReset>().build();
+        for i in 1..=2 {
+            let output = session
+                .dispatch::<FailingReset>("app-a", vec![])
+                .await
+                .unwrap();
+            assert_eq!(output, vec![42]);
+            assert_eq!(RESET_PREPARED.load(Ordering::SeqCst), i);
+            assert_eq!(RESET_RELEASED.load(Ordering::SeqCst), i);
+        }
+        session.close().await;
+        assert_eq!(RESET_RELEASED.load(Ordering::SeqCst), 2);
+    }
+
+    #[actix_rt::test]
+    async fn prepares_once_and_releases_on_close() {
+        let session = Session::builder().backend::<Counting>().build();
+        for _ in 0..3 {
+            let output = session.dispatch::<Counting>("app-a", vec![]).await.unwrap();
+            assert_eq!(output, vec![42]);
+        }
+        session
+            .dispatch::<Counting>("app-b::f", vec![])
+            .await
+            .unwrap();
+        assert_eq!(PREPARED.load(Ordering::SeqCst), 2);
+        assert_eq!(RELEASED.load(Ordering::SeqCst), 0);
+        session.close().await;
+        assert_eq!(RELEASED.load(Ordering::SeqCst), 2);
+    }
+}
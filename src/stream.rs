@@ -0,0 +1,160 @@
+//! Streams of items returned by remote functions.
+
+use crate::Error;
+use anyhow::anyhow;
+use futures::{
+    channel::mpsc::Receiver,
+    stream::{self, BoxStream, StreamExt},
+};
+use std::convert::TryInto;
+
+/// Stream of items produced by a remote function returning `impl Iterator<Item = T>`.
+///
+/// Every item is deserialized only once it is polled, and the stream ends at the first error.
+pub type Stream<T> = BoxStream<'static, Result<T, Error>>;
+
+/// Splits the next length-delimited record off `data` starting at `offset`.
+fn next_record(data: &[u8], offset: usize) -> Option<Result<(&[u8], usize), Error>> {
+    let rest = &data[offset..];
+    if rest.is_empty() {
+        return None;
+    }
+    let malformed = || Error::Serialization(anyhow!("malformed output record"));
+    let len = match rest.get(..4) {
+        Some(len) => u32::from_le_bytes(len.try_into().unwrap()) as usize,
+        None => return Some(Err(malformed())),
+    };
+    let end = match len.checked_add(4) {
+        Some(end) => end,
+        None => return Some(Err(malformed())),
+    };
+    match rest.get(4..end) {
+        Some(record) => Some(Ok((record, offset + end))),
+        None => Some(Err(malformed())),
+    }
+}
+
+/// Turns the output of a remote function returning an iterator, i.e., a sequence of records
+/// each prefixed with its length as `u32` in little endian, into a stream of items decoded
+/// with `decode`.
+#[doc(hidden)]
+pub fn from_records<T, F>(data: Vec<u8>, decode: F) -> Stream<T>
+where
+    T: Send + 'static,
+    F: Fn(Vec<u8>) -> Result<T, Error> + Send + 'static,
+{
+    stream::unfold(Some((data, 0)), move |state| {
+        let res = state.and_then(|(data, offset)| match next_record(&data, offset)? {
+            Ok((record, offset)) => {
+                let item = decode(record.to_vec());
+                let next = if item.is_ok() {
+                    Some((data, offset))
+                } else {
+                    None
+                };
+                Some((item, next))
+            }
+            Err(err) => Some((Err(err), None)),
+        });
+        futures::future::ready(res)
+    })
+    .boxed()
+}
+
+/// Turns the serialized items sent by a remote function running natively into a stream of
+/// items decoded with `decode`.
+#[doc(hidden)]
+pub fn from_channel<T, F>(rx: Receiver<Result<Vec<u8>, Error>>, decode: F) -> Stream<T>
+where
+    T: Send + 'static,
+    F: Fn(Vec<u8>) -> Result<T, Error> + Send + 'static,
+{
+    rx.map(move |data| data.and_then(&decode))
+        .scan(false, |failed, item| {
+            let res = if *failed { None } else { Some(item) };
+            *failed = matches!(res, Some(Err(_)));
+            futures::future::ready(res)
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{channel::mpsc, executor::block_on, SinkExt};
+
+    fn record(data: &[u8]) -> Vec<u8> {
+        let mut record = (data.len() as u32).to_le_bytes().to_vec();
+        record.extend_from_slice(data);
+        record
+    }
+
+    fn decode(data: Vec<u8>) -> Result<String, Error> {
+        String::from_utf8(data).map_err(|err| Error::Serialization(err.into()))
+    }
+
+    fn collect(data: Vec<u8>) -> Vec<Result<String, Error>> {
+        block_on(from_records(data, decode).collect())
+    }
+
+    #[test]
+    fn decodes_records() {
+        let data = [record(b"a"), record(b""), record(b"bc")].concat();
+        let items: Vec<_> = collect(data).into_iter().map(Result::unwrap).collect();
+        assert_eq!(items, vec!["a", "", "bc"]);
+        assert!(collect(vec![]).is_empty());
+    }
+
+    #[test]
+    fn ends_at_truncated_length() {
+        let data = [record(b"a"), vec![1, 0]].concat();
+        let items = collect(data);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_deref().unwrap(), "a");
+        assert!(matches!(items[1], Err(Error::Serialization(_))));
+    }
+
+    #[test]
+    fn ends_at_truncated_record() {
+        let mut data = [record(b"a"), record(b"bcd")].concat();
+        data.pop();
+        let items = collect(data);
+        assert_eq!(items.len(), 2);
+        assert!(matches!(items[1], Err(Error::Serialization(_))));
+    }
+
+    #[test]
+    fn ends_at_overflowing_length() {
+        let data = [
+            u32::MAX.to_le_bytes().to_vec(),
+            b"abc".to_vec(),
+            record(b"a"),
+        ]
+        .concat();
+        let items = collect(data);
+        assert_eq!(items.len(), 1);
+        assert!(matches!(items[0], Err(Error::Serialization(_))));
+    }
+
+    #[test]
+    fn ends_at_first_decoding_error() {
+        let data = [record(b"a"), record(&[0xff]), record(b"b")].concat();
+        let items = collect(data);
+        assert_eq!(items.len(), 2);
+        assert!(items[1].is_err());
+    }
+
+    #[test]
+    fn ends_channel_at_first_error() {
+        let (mut tx, rx) = mpsc::channel(4);
+        let items: Vec<_> = block_on(async {
+            tx.send(Ok(b"a".to_vec())).await.unwrap();
+            tx.send(Ok(vec![0xff])).await.unwrap();
+            tx.send(Ok(b"b".to_vec())).await.unwrap();
+            drop(tx);
+            from_channel(rx, decode).collect().await
+        });
+        assert_eq!(items.len(), 2);
+        assert!(items[1].is_err());
+    }
+}
@@ -0,0 +1,8 @@
+use gfaas::remote_fn;
+
+#[remote_fn]
+fn hello(#[allow(unused)] input: String) -> String {
+    String::new()
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use gfaas::remote_fn;
+
+#[remote_fn]
+fn first<const N: usize>(input: [u32; N]) -> u32 {
+    input[0]
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use gfaas::remote_fn;
+
+#[remote_fn(instantiate(u32, f64, u32))]
+fn identity<T>(input: T) -> T {
+    input
+}
+
+fn main() {}
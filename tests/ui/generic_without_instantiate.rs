@@ -0,0 +1,8 @@
+use gfaas::remote_fn;
+
+#[remote_fn]
+fn identity<T>(input: T) -> T {
+    input
+}
+
+fn main() {}
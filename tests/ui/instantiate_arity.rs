@@ -0,0 +1,8 @@
+use gfaas::remote_fn;
+
+#[remote_fn(instantiate((u32, u64, u8)))]
+fn pair<A, B>(a: A, b: B) -> (A, B) {
+    (a, b)
+}
+
+fn main() {}
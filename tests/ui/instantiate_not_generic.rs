@@ -0,0 +1,8 @@
+use gfaas::remote_fn;
+
+#[remote_fn(instantiate(u32))]
+fn identity(input: u32) -> u32 {
+    input
+}
+
+fn main() {}
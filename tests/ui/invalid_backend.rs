@@ -0,0 +1,8 @@
+use gfaas::remote_fn;
+
+#[remote_fn(backend = "my backend")]
+fn hello(input: String) -> String {
+    input
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use gfaas::remote_fn;
+
+#[remote_fn(constraints = "(golem.inf.cpu.threads>)")]
+fn prove(x: u32) -> u32 {
+    x
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use gfaas::remote_fn;
+
+#[remote_fn(group = "maths::basic")]
+fn double(x: u32) -> u32 {
+    x * 2
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use gfaas::remote_fn;
+
+#[remote_fn(budget = true)]
+fn hello(input: String) -> String {
+    input
+}
+
+fn main() {}
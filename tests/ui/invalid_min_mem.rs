@@ -0,0 +1,8 @@
+use gfaas::remote_fn;
+
+#[remote_fn(min_mem_gib = -2.0)]
+fn prove(x: u32) -> u32 {
+    x
+}
+
+fn main() {}
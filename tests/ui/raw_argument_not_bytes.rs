@@ -0,0 +1,8 @@
+use gfaas::remote_fn;
+
+#[remote_fn]
+fn hello(#[raw] input: String) -> String {
+    input
+}
+
+fn main() {}
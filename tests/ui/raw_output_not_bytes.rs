@@ -0,0 +1,9 @@
+use gfaas::remote_fn;
+
+#[remote_fn]
+#[raw]
+fn hello(input: Vec<u8>) -> String {
+    String::from_utf8(input).unwrap()
+}
+
+fn main() {}
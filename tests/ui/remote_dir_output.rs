@@ -0,0 +1,8 @@
+use gfaas::remote_fn;
+
+#[remote_fn]
+fn hello(input: String) -> gfaas::RemoteDir {
+    gfaas::RemoteDir::new(input)
+}
+
+fn main() {}
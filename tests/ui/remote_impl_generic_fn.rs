@@ -0,0 +1,21 @@
+use gfaas::remote_impl;
+
+#[remote_impl]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+#[remote_impl]
+impl Point {
+    fn scale<T: Into<f64>>(&self, factor: T) -> Point {
+        let factor = factor.into();
+        Point {
+            x: self.x * factor,
+            y: self.y * factor,
+        }
+    }
+}
+
+fn main() {}
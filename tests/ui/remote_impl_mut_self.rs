@@ -0,0 +1,16 @@
+use gfaas::remote_impl;
+
+#[remote_impl]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Counter {
+    count: u32,
+}
+
+#[remote_impl]
+impl Counter {
+    fn increment(&mut self) {
+        self.count += 1;
+    }
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use gfaas::remote_impl;
+
+struct Answer;
+
+#[remote_impl]
+impl Default for Answer {
+    fn default() -> Self {
+        Answer
+    }
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use gfaas::remote_impl;
+
+#[remote_impl(budget = 10)]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use gfaas::remote_mod;
+
+#[remote_mod]
+mod outer {
+    mod inner {
+        #[gfaas::remote_fn]
+        fn hello(input: String) -> String {
+            input
+        }
+    }
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use gfaas::remote_fn;
+
+#[remote_fn(instances(u32))]
+fn scale<T>(input: T) -> T {
+    input
+}
+
+fn main() {}